@@ -1,158 +1,2663 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::{Read, Write};
+use std::num::Wrapping;
+use std::ops::{BitAnd, BitOr, BitXor, Not, Range};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
 
 pub type Value = i32;
 pub type Result = std::result::Result<(), Error>;
+pub type Stack<C = Value> = Vec<C>;
 
-const BASE_TOKENS: &[&str] = &["+", "-", "*", "/", "DUP", "OVER", "DROP", "SWAP"];
-const IDX_PREFIX: char = '@';
+const IMAGE_MAGIC: &[u8; 4] = b"FRTH";
+const IMAGE_VERSION: u32 = 4;
 
-fn is_base_token(word: &str) -> bool {
-    BASE_TOKENS.contains(&word)
+// Immediate words run while compiling and may compile in turn, so each nested `run` also uses the native stack.
+const MAX_NESTED_RUNS: usize = 128;
+
+const BLANKS: &str = "                                                                ";
+
+const BASE_TOKENS: &[(&str, Builtin)] = &[
+    ("+", Builtin::Add),
+    ("-", Builtin::Sub),
+    ("*", Builtin::Mul),
+    ("/", Builtin::Div),
+    ("DUP", Builtin::Dup),
+    ("OVER", Builtin::Over),
+    ("DROP", Builtin::Drop),
+    ("SWAP", Builtin::Swap),
+    ("=", Builtin::Eq),
+    ("<", Builtin::Lt),
+    (">", Builtin::Gt),
+    ("<>", Builtin::Ne),
+    ("0=", Builtin::ZeroEq),
+    ("0<", Builtin::ZeroLt),
+    ("AND", Builtin::And),
+    ("OR", Builtin::Or),
+    ("XOR", Builtin::Xor),
+    ("INVERT", Builtin::Invert),
+    ("NEGATE", Builtin::Negate),
+    ("ABS", Builtin::Abs),
+    ("MIN", Builtin::Min),
+    ("MAX", Builtin::Max),
+    ("MOD", Builtin::Mod),
+    ("/MOD", Builtin::DivMod),
+    ("*/", Builtin::MulDiv),
+    ("ROT", Builtin::Rot),
+    ("-ROT", Builtin::MinusRot),
+    ("NIP", Builtin::Nip),
+    ("TUCK", Builtin::Tuck),
+    ("2DUP", Builtin::TwoDup),
+    ("2DROP", Builtin::TwoDrop),
+    ("2SWAP", Builtin::TwoSwap),
+    ("2OVER", Builtin::TwoOver),
+    ("?DUP", Builtin::QuestionDup),
+    ("DEPTH", Builtin::Depth),
+    ("PICK", Builtin::Pick),
+    ("ROLL", Builtin::Roll),
+    ("@", Builtin::Fetch),
+    ("!", Builtin::Store),
+    ("+!", Builtin::PlusStore),
+    (",", Builtin::Comma),
+    ("ALLOT", Builtin::Allot),
+    ("HERE", Builtin::Here),
+    (".", Builtin::Dot),
+    (".S", Builtin::DotS),
+    ("EMIT", Builtin::Emit),
+    ("CR", Builtin::Cr),
+    ("SPACE", Builtin::Space),
+    ("SPACES", Builtin::Spaces),
+    ("EXECUTE", Builtin::Execute),
+    ("CATCH", Builtin::Catch),
+    ("THROW", Builtin::Throw),
+    ("WORDS", Builtin::Words),
+    ("TYPE", Builtin::Type),
+    ("BASE", Builtin::Base),
+    ("HEX", Builtin::Hex),
+    ("DECIMAL", Builtin::Decimal),
+    ("IMMEDIATE", Builtin::Immediate),
+    ("D+", Builtin::DAdd),
+    ("D-", Builtin::DSub),
+    ("DNEGATE", Builtin::DNegate),
+    ("D.", Builtin::DDot),
+    ("M*", Builtin::MMul),
+    ("UM*", Builtin::UMMul),
+    ("UM/MOD", Builtin::UMDivMod),
+    ("SM/REM", Builtin::SMDivRem),
+    ("FM/MOD", Builtin::FMDivMod),
+    ("2@", Builtin::TwoFetch),
+    ("2!", Builtin::TwoStore),
+    #[cfg(feature = "float")]
+    ("F+", Builtin::FAdd),
+    #[cfg(feature = "float")]
+    ("F-", Builtin::FSub),
+    #[cfg(feature = "float")]
+    ("F*", Builtin::FMul),
+    #[cfg(feature = "float")]
+    ("F/", Builtin::FDiv),
+    #[cfg(feature = "float")]
+    ("FDUP", Builtin::FDup),
+    #[cfg(feature = "float")]
+    ("FDROP", Builtin::FDrop),
+    #[cfg(feature = "float")]
+    ("FSWAP", Builtin::FSwap),
+    #[cfg(feature = "float")]
+    ("F.", Builtin::FDot),
+    #[cfg(feature = "float")]
+    ("FSQRT", Builtin::FSqrt),
+    #[cfg(feature = "float")]
+    ("F<", Builtin::FLt),
+];
+
+const CONTROL_WORDS: &[&str] = &[
+    ";", "IF", "ELSE", "THEN", "DO", "LOOP", "+LOOP", "I", "J", "BEGIN", "UNTIL", "WHILE", "REPEAT",
+    ">R", "R>", "R@", "EXIT", "RECURSE", "[']", "[CHAR]",
+    "[", "LITERAL", "POSTPONE",
+];
+
+const DEFINING_WORDS: &[&str] = &["VARIABLE", "CONSTANT", "CREATE", "MARKER", "2VARIABLE", "2CONSTANT"];
+
+const TOP_LEVEL_WORDS: &[&str] = &["SEE", "FORGET", "INCLUDE", "REQUIRE"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Builtin {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Dup,
+    Over,
+    Drop,
+    Swap,
+    Eq,
+    Lt,
+    Gt,
+    Ne,
+    ZeroEq,
+    ZeroLt,
+    And,
+    Or,
+    Xor,
+    Invert,
+    Negate,
+    Abs,
+    Min,
+    Max,
+    Mod,
+    DivMod,
+    MulDiv,
+    Rot,
+    MinusRot,
+    Nip,
+    Tuck,
+    TwoDup,
+    TwoDrop,
+    TwoSwap,
+    TwoOver,
+    QuestionDup,
+    Depth,
+    Pick,
+    Roll,
+    Fetch,
+    Store,
+    PlusStore,
+    Comma,
+    Allot,
+    Here,
+    Dot,
+    DotS,
+    Emit,
+    Cr,
+    Space,
+    Spaces,
+    Execute,
+    Catch,
+    Throw,
+    Words,
+    Type,
+    Base,
+    Hex,
+    Decimal,
+    Immediate,
+    DAdd,
+    DSub,
+    DNegate,
+    DDot,
+    MMul,
+    UMMul,
+    UMDivMod,
+    SMDivRem,
+    FMDivMod,
+    TwoFetch,
+    TwoStore,
+    #[cfg(feature = "float")]
+    FAdd,
+    #[cfg(feature = "float")]
+    FSub,
+    #[cfg(feature = "float")]
+    FMul,
+    #[cfg(feature = "float")]
+    FDiv,
+    #[cfg(feature = "float")]
+    FDup,
+    #[cfg(feature = "float")]
+    FDrop,
+    #[cfg(feature = "float")]
+    FSwap,
+    #[cfg(feature = "float")]
+    FDot,
+    #[cfg(feature = "float")]
+    FSqrt,
+    #[cfg(feature = "float")]
+    FLt,
+}
+
+pub trait Cell:
+    Copy + Ord + Default + fmt::Debug + 'static
+    + BitAnd<Output = Self> + BitOr<Output = Self> + BitXor<Output = Self> + Not<Output = Self>
+{
+    const MIN: Self;
+    const MAX: Self;
+    const BITS: u32;
+
+    fn from_i128(value: i128) -> Option<Self>;
+    fn wrapping_from_i128(value: i128) -> Self;
+    fn to_i128(self) -> i128;
+    fn parse(digits: &str, radix: u32) -> Option<Self>;
+    fn is_negative(self) -> bool;
+
+    fn checked_add(self, other: Self) -> Option<Self>;
+    fn checked_sub(self, other: Self) -> Option<Self>;
+    fn checked_mul(self, other: Self) -> Option<Self>;
+    fn checked_div(self, other: Self) -> Option<Self>;
+    fn checked_rem(self, other: Self) -> Option<Self>;
+    fn checked_neg(self) -> Option<Self>;
+    fn checked_abs(self) -> Option<Self>;
+    fn wrapping_add(self, other: Self) -> Self;
+    fn wrapping_sub(self, other: Self) -> Self;
+    fn wrapping_mul(self, other: Self) -> Self;
+    fn wrapping_div(self, other: Self) -> Self;
+    fn wrapping_rem(self, other: Self) -> Self;
+    fn wrapping_neg(self) -> Self;
+    fn wrapping_abs(self) -> Self;
+
+    fn format(self, radix: u32) -> String {
+        let value = self.to_i128();
+        let mut magnitude = value.unsigned_abs();
+        let mut digits = Vec::new();
+        loop {
+            let digit = char::from_digit((magnitude % radix as u128) as u32, radix).unwrap();
+            digits.push(digit.to_ascii_uppercase());
+            magnitude /= radix as u128;
+            if magnitude == 0 {
+                break;
+            }
+        }
+        if value < 0 {
+            digits.push('-');
+        }
+        digits.iter().rev().collect()
+    }
+}
+
+macro_rules! signed_cell {
+    ($($cell:ty),*) => {$(
+        #[allow(clippy::unnecessary_cast)]
+        impl Cell for $cell {
+            const MIN: Self = <$cell>::MIN;
+            const MAX: Self = <$cell>::MAX;
+            const BITS: u32 = <$cell>::BITS;
+
+            fn from_i128(value: i128) -> Option<Self> { <$cell>::try_from(value).ok() }
+            fn wrapping_from_i128(value: i128) -> Self { value as $cell }
+            fn to_i128(self) -> i128 { self as i128 }
+            fn parse(digits: &str, radix: u32) -> Option<Self> { <$cell>::from_str_radix(digits, radix).ok() }
+            fn is_negative(self) -> bool { self < 0 }
+
+            fn checked_add(self, other: Self) -> Option<Self> { <$cell>::checked_add(self, other) }
+            fn checked_sub(self, other: Self) -> Option<Self> { <$cell>::checked_sub(self, other) }
+            fn checked_mul(self, other: Self) -> Option<Self> { <$cell>::checked_mul(self, other) }
+            fn checked_div(self, other: Self) -> Option<Self> { <$cell>::checked_div(self, other) }
+            fn checked_rem(self, other: Self) -> Option<Self> { <$cell>::checked_rem(self, other) }
+            fn checked_neg(self) -> Option<Self> { <$cell>::checked_neg(self) }
+            fn checked_abs(self) -> Option<Self> { <$cell>::checked_abs(self) }
+            fn wrapping_add(self, other: Self) -> Self { <$cell>::wrapping_add(self, other) }
+            fn wrapping_sub(self, other: Self) -> Self { <$cell>::wrapping_sub(self, other) }
+            fn wrapping_mul(self, other: Self) -> Self { <$cell>::wrapping_mul(self, other) }
+            fn wrapping_div(self, other: Self) -> Self { <$cell>::wrapping_div(self, other) }
+            fn wrapping_rem(self, other: Self) -> Self { <$cell>::wrapping_rem(self, other) }
+            fn wrapping_neg(self) -> Self { <$cell>::wrapping_neg(self) }
+            fn wrapping_abs(self) -> Self { <$cell>::wrapping_abs(self) }
+        }
+    )*};
+}
+
+signed_cell!(i32, i64, i128);
+
+// Modular arithmetic never overflows, so the checked operations only fail on division by zero.
+impl Cell for Wrapping<u16> {
+    const MIN: Self = Wrapping(u16::MIN);
+    const MAX: Self = Wrapping(u16::MAX);
+    const BITS: u32 = u16::BITS;
+
+    fn from_i128(value: i128) -> Option<Self> {
+        (i128::from(i16::MIN)..=i128::from(u16::MAX)).contains(&value).then(|| Self::wrapping_from_i128(value))
+    }
+    fn wrapping_from_i128(value: i128) -> Self { Wrapping(value as u16) }
+    fn to_i128(self) -> i128 { i128::from(self.0) }
+    fn parse(digits: &str, radix: u32) -> Option<Self> {
+        i128::from_str_radix(digits, radix).ok().and_then(Self::from_i128)
+    }
+    fn is_negative(self) -> bool { self.0 & 0x8000 != 0 }
+
+    fn checked_add(self, other: Self) -> Option<Self> { Some(self + other) }
+    fn checked_sub(self, other: Self) -> Option<Self> { Some(self - other) }
+    fn checked_mul(self, other: Self) -> Option<Self> { Some(self * other) }
+    fn checked_div(self, other: Self) -> Option<Self> { self.0.checked_div(other.0).map(Wrapping) }
+    fn checked_rem(self, other: Self) -> Option<Self> { self.0.checked_rem(other.0).map(Wrapping) }
+    fn checked_neg(self) -> Option<Self> { Some(-self) }
+    fn checked_abs(self) -> Option<Self> { Some(self) }
+    fn wrapping_add(self, other: Self) -> Self { self + other }
+    fn wrapping_sub(self, other: Self) -> Self { self - other }
+    fn wrapping_mul(self, other: Self) -> Self { self * other }
+    fn wrapping_div(self, other: Self) -> Self { self / other }
+    fn wrapping_rem(self, other: Self) -> Self { self % other }
+    fn wrapping_neg(self) -> Self { -self }
+    fn wrapping_abs(self) -> Self { self }
+}
+
+fn definition_name<C: Cell>(word: Option<&str>) -> std::result::Result<String, Error> {
+    match word {
+        Some(key) if C::parse(key, 10).is_none() => Ok(key.to_ascii_uppercase()),
+        _ => Err(Error::InvalidWord),
+    }
+}
+
+fn is_char_literal(token: &str) -> bool {
+    token.len() > 2 && token.starts_with('\'') && token.ends_with('\'') && token.chars().count() == 3
+}
+
+fn flag<C: Cell>(condition: bool) -> C {
+    if condition { !C::default() } else { C::default() }
+}
+
+fn cell<C: Cell>(value: usize) -> C {
+    C::wrapping_from_i128(value as i128)
+}
+
+// System variables sit outside the data space so they never show up in `memory()`.
+fn base_address<C: Cell>() -> C {
+    if C::MIN < C::default() { C::MIN } else { C::MAX }
+}
+
+fn signed<C: Cell>(value: C) -> i128 {
+    value.to_i128() << (128 - C::BITS) >> (128 - C::BITS)
+}
+
+fn unsigned<C: Cell>(value: C) -> u128 {
+    value.to_i128() as u128 & (u128::MAX >> (128 - C::BITS))
+}
+
+// A double is a low cell under a high cell. With 128-bit cells only doubles that fit one cell are usable.
+fn join<C: Cell>(low: C, high: C) -> std::result::Result<i128, Error> {
+    if C::BITS == 128 {
+        return if high == flag(low.is_negative()) { Ok(low.to_i128()) } else { Err(Error::Overflow) };
+    }
+    Ok(signed(high) << C::BITS | unsigned(low) as i128)
+}
+
+fn join_unsigned<C: Cell>(low: C, high: C) -> std::result::Result<u128, Error> {
+    if C::BITS == 128 {
+        return if high == C::default() { Ok(unsigned(low)) } else { Err(Error::Overflow) };
+    }
+    Ok(unsigned(high) << C::BITS | unsigned(low))
+}
+
+fn split<C: Cell>(value: i128) -> (C, C) {
+    let high = if C::BITS == 128 { flag(value < 0) } else { C::wrapping_from_i128(value >> C::BITS) };
+    (C::wrapping_from_i128(value), high)
+}
+
+fn fits_double<C: Cell>(value: i128) -> bool {
+    C::BITS >= 64 || matches!(value >> (2 * C::BITS - 1), 0 | -1)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Instruction<C> {
+    Push(C),
+    Builtin(Builtin),
+    Call(usize),
+    Branch(usize),
+    BranchIfZero(usize),
+    Do,
+    Loop(usize),
+    PlusLoop(usize),
+    LoopIndex(usize),
+    Print(usize),
+    ToR,
+    FromR,
+    RFetch,
+    Exit,
+    Recurse,
+    Native(usize),
+    Forget(usize),
+    CompileWord(usize),
+    CompileToken(usize),
+    PushXt(usize),
+    PushString(usize, usize),
+    // Held as raw bits so instructions stay `Eq`.
+    #[cfg(feature = "float")]
+    FPush(u64),
+}
+
+struct Frame<C> {
+    word: usize,
+    pc: usize,
+    loop_depth: usize,
+    return_depth: usize,
+    catch: Option<usize>,
+    before: Option<Vec<C>>,
+}
+
+fn current_frame<C>(frames: &mut [Frame<C>]) -> std::result::Result<&mut Frame<C>, Error> {
+    frames.last_mut().ok_or(Error::ControlStructureMismatch)
+}
+
+struct Tokens<'a> {
+    input: &'a str,
+    position: usize,
+}
+
+impl<'a> Tokens<'a> {
+    fn new(input: &'a str) -> Self {
+        Tokens { input, position: 0 }
+    }
+
+    fn next_spanned(&mut self) -> Option<(usize, &'a str)> {
+        let rest = self.input[self.position..].trim_start();
+        let start = self.input.len() - rest.len();
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        self.position = start + end;
+        (end > 0).then(|| (start, &rest[..end]))
+    }
+
+    fn skip_line(&mut self) {
+        let rest = &self.input[self.position..];
+        self.position += rest.find('\n').unwrap_or(rest.len());
+    }
+
+    fn parse_until(&mut self, delimiter: char) -> &'a str {
+        let rest = &self.input[self.position..];
+        let rest = rest.strip_prefix(|ch: char| ch.is_whitespace()).unwrap_or(rest);
+        let start = self.input.len() - rest.len();
+        let (text, consumed) = match rest.find(delimiter) {
+            Some(end) => (&rest[..end], end + delimiter.len_utf8()),
+            None => (rest, rest.len()),
+        };
+        self.position = start + consumed;
+        text
+    }
+}
+
+impl<'a> Iterator for Tokens<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        self.next_spanned().map(|(_, token)| token)
+    }
+}
+
+#[derive(Clone)]
+enum Word<C> {
+    Builtin(Instruction<C>),
+    Colon(Vec<Instruction<C>>),
+    Native(Instruction<C>, Native<C>),
+}
+
+type NativeFn<C> = dyn Fn(&mut Stack<C>) -> Result;
+
+#[derive(Clone)]
+struct Native<C> {
+    arity: usize,
+    function: Rc<NativeFn<C>>,
+}
+
+#[derive(Clone)]
+struct Entry<C> {
+    name: String,
+    word: Word<C>,
+    here: usize,
+    immediate: bool,
+    defined_by: Option<&'static str>,
+}
+
+impl<C> Word<C> {
+    fn instructions(&self) -> &[Instruction<C>] {
+        match self {
+            Word::Colon(instructions) => instructions,
+            Word::Builtin(instruction) | Word::Native(instruction, _) => std::slice::from_ref(instruction),
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Definition<C> {
+    key: String,
+    instructions: Vec<Instruction<C>>,
+    control: Vec<Control>,
+    here: usize,
+    suspended: bool,
+}
+
+// Execution started by `Forth::start`, kept between calls to `Forth::step`.
+struct Session<C> {
+    input: String,
+    position: usize,
+    span: Range<usize>,
+    frames: Vec<Frame<C>>,
+    loop_depth: usize,
+    return_depth: usize,
+}
+
+struct Snapshot<C> {
+    stack: Vec<C>,
+    memory: Vec<C>,
+    dictionary_idx: HashMap<String, usize>,
+    dictionary: Vec<Rc<Entry<C>>>,
+    strings_len: usize,
+    compiling: Option<Definition<C>>,
+    base: u32,
+    included: HashSet<String>,
+    #[cfg(feature = "float")]
+    float_stack: Vec<f64>,
 }
 
-pub struct Forth {
-    stack: Vec<Value>,
-    user_operations_idx: HashMap<String, usize>,
-    user_operations: Vec<String>,
-    seq_id: usize,
-}
+impl<C> Definition<C> {
+    fn compile_control(&mut self, word: &str) -> std::result::Result<bool, Error> {
+        match word {
+            "IF" => {
+                self.control.push(Control::If(self.instructions.len()));
+                self.instructions.push(Instruction::BranchIfZero(0));
+            },
+            "ELSE" => {
+                let Some(Control::If(if_idx)) = self.control.pop() else {
+                    return Err(Error::ControlStructureMismatch);
+                };
+                self.control.push(Control::Else(self.instructions.len()));
+                self.instructions.push(Instruction::Branch(0));
+                self.instructions[if_idx] = Instruction::BranchIfZero(self.instructions.len());
+            },
+            "THEN" => match self.control.pop() {
+                Some(Control::If(idx)) =>
+                    self.instructions[idx] = Instruction::BranchIfZero(self.instructions.len()),
+                Some(Control::Else(idx)) =>
+                    self.instructions[idx] = Instruction::Branch(self.instructions.len()),
+                _ => return Err(Error::ControlStructureMismatch),
+            },
+            "DO" => {
+                self.instructions.push(Instruction::Do);
+                self.control.push(Control::Do(self.instructions.len()));
+            },
+            "LOOP" | "+LOOP" => {
+                let Some(Control::Do(body)) = self.control.pop() else {
+                    return Err(Error::ControlStructureMismatch);
+                };
+                self.instructions.push(if word == "LOOP" {
+                    Instruction::Loop(body)
+                } else {
+                    Instruction::PlusLoop(body)
+                });
+            },
+            "I" | "J" => {
+                let depth = if word == "I" { 0 } else { 1 };
+                let loops = self.control.iter().filter(|c| matches!(c, Control::Do(_))).count();
+                if loops <= depth {
+                    return Err(Error::ControlStructureMismatch);
+                }
+                self.instructions.push(Instruction::LoopIndex(depth));
+            },
+            "BEGIN" => self.control.push(Control::Begin(self.instructions.len())),
+            "UNTIL" => {
+                let Some(Control::Begin(start)) = self.control.pop() else {
+                    return Err(Error::ControlStructureMismatch);
+                };
+                self.instructions.push(Instruction::BranchIfZero(start));
+            },
+            "WHILE" => {
+                let Some(Control::Begin(_)) = self.control.last() else {
+                    return Err(Error::ControlStructureMismatch);
+                };
+                self.control.push(Control::While(self.instructions.len()));
+                self.instructions.push(Instruction::BranchIfZero(0));
+            },
+            "REPEAT" => {
+                let (Some(Control::While(exit)), Some(Control::Begin(start))) =
+                    (self.control.pop(), self.control.pop()) else {
+                    return Err(Error::ControlStructureMismatch);
+                };
+                self.instructions.push(Instruction::Branch(start));
+                self.instructions[exit] = Instruction::BranchIfZero(self.instructions.len());
+            },
+            ">R" => self.instructions.push(Instruction::ToR),
+            "R>" => self.instructions.push(Instruction::FromR),
+            "R@" => self.instructions.push(Instruction::RFetch),
+            "EXIT" => self.instructions.push(Instruction::Exit),
+            "RECURSE" => self.instructions.push(Instruction::Recurse),
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+}
+
+impl<C: Cell> Instruction<C> {
+    fn encode(self, out: &mut Vec<u8>) {
+        let (tag, operand) = match self {
+            Instruction::Push(value) => {
+                out.push(0);
+                out.extend_from_slice(&value.to_i128().to_le_bytes());
+                return;
+            },
+            Instruction::Builtin(op) => (1, BASE_TOKENS.iter().position(|(_, b)| *b == op).unwrap() as u32),
+            Instruction::Call(idx) => (2, idx as u32),
+            Instruction::Branch(target) => (3, target as u32),
+            Instruction::BranchIfZero(target) => (4, target as u32),
+            Instruction::Do => (5, 0),
+            Instruction::Loop(body) => (6, body as u32),
+            Instruction::PlusLoop(body) => (7, body as u32),
+            Instruction::LoopIndex(depth) => (8, depth as u32),
+            Instruction::Print(idx) => (9, idx as u32),
+            Instruction::ToR => (10, 0),
+            Instruction::FromR => (11, 0),
+            Instruction::RFetch => (12, 0),
+            Instruction::Exit => (13, 0),
+            Instruction::Recurse => (14, 0),
+            Instruction::Native(idx) => (15, idx as u32),
+            Instruction::Forget(idx) => (16, idx as u32),
+            Instruction::CompileWord(idx) => (17, idx as u32),
+            Instruction::CompileToken(idx) => (18, idx as u32),
+            Instruction::PushXt(idx) => (20, idx as u32),
+            Instruction::PushString(address, idx) => {
+                out.push(21);
+                encode_u32(out, idx);
+                encode_u32(out, address);
+                return;
+            },
+            #[cfg(feature = "float")]
+            Instruction::FPush(bits) => {
+                out.push(19);
+                out.extend_from_slice(&bits.to_le_bytes());
+                return;
+            },
+        };
+        out.push(tag);
+        out.extend_from_slice(&operand.to_le_bytes());
+    }
+}
+
+fn encode_u32(out: &mut Vec<u8>, value: usize) {
+    out.extend_from_slice(&(value as u32).to_le_bytes());
+}
+
+fn encode_str(out: &mut Vec<u8>, text: &str) {
+    encode_u32(out, text.len());
+    out.extend_from_slice(text.as_bytes());
+}
+
+struct ImageReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> ImageReader<'a> {
+    fn take(&mut self, len: usize) -> std::result::Result<&'a [u8], Error> {
+        let end = self.position.checked_add(len).filter(|end| *end <= self.bytes.len())
+            .ok_or(Error::Image(ImageError::Corrupt))?;
+        let bytes = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> std::result::Result<u8, Error> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> std::result::Result<u32, Error> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn len(&mut self) -> std::result::Result<usize, Error> {
+        Ok(self.u32()? as usize)
+    }
+
+    fn str(&mut self) -> std::result::Result<String, Error> {
+        let len = self.len()?;
+        String::from_utf8(self.take(len)?.to_vec()).map_err(|_| Error::Image(ImageError::Corrupt))
+    }
+
+    // Cells are stored at full width so an image can move between cell types when its values fit.
+    fn value<C: Cell>(&mut self) -> std::result::Result<C, Error> {
+        let value = i128::from_le_bytes(self.take(16)?.try_into().unwrap());
+        C::from_i128(value).ok_or(Error::Image(ImageError::Corrupt))
+    }
+
+    fn values<C: Cell>(&mut self) -> std::result::Result<Vec<C>, Error> {
+        (0..self.len()?).map(|_| self.value()).collect()
+    }
+
+    // Only the shapes `compile` can produce are accepted, so a loaded image cannot index out of bounds.
+    fn instruction<C: Cell>(&mut self, dictionary: &[Rc<Entry<C>>], strings: usize, length: usize)
+        -> std::result::Result<Instruction<C>, Error> {
+        let tag = self.u8()?;
+        if tag == 0 {
+            return Ok(Instruction::Push(self.value()?));
+        }
+        #[cfg(feature = "float")]
+        if tag == 19 {
+            return Ok(Instruction::FPush(u64::from_le_bytes(self.take(8)?.try_into().unwrap())));
+        }
+        let idx = self.len()?;
+        let instruction = match tag {
+            1 if idx < BASE_TOKENS.len() => Instruction::Builtin(BASE_TOKENS[idx].1),
+            2 if idx < dictionary.len() => Instruction::Call(idx),
+            3 if idx <= length => Instruction::Branch(idx),
+            4 if idx <= length => Instruction::BranchIfZero(idx),
+            5 => Instruction::Do,
+            6 if idx <= length => Instruction::Loop(idx),
+            7 if idx <= length => Instruction::PlusLoop(idx),
+            8 if idx <= 1 => Instruction::LoopIndex(idx),
+            9 if idx < strings => Instruction::Print(idx),
+            10 => Instruction::ToR,
+            11 => Instruction::FromR,
+            12 => Instruction::RFetch,
+            13 => Instruction::Exit,
+            14 => Instruction::Recurse,
+            15 if matches!(dictionary.get(idx).map(|entry| &entry.word), Some(Word::Native(..))) =>
+                Instruction::Native(idx),
+            16 if (BASE_TOKENS.len()..dictionary.len()).contains(&idx) => Instruction::Forget(idx),
+            17 if idx < dictionary.len() => Instruction::CompileWord(idx),
+            18 if idx < strings => Instruction::CompileToken(idx),
+            20 if idx < dictionary.len() => Instruction::PushXt(idx),
+            21 if idx < strings => Instruction::PushString(self.len()?, idx),
+            _ => return Err(Error::Image(ImageError::Corrupt)),
+        };
+        Ok(instruction)
+    }
+}
+
+enum Output {
+    Buffer(String),
+    Sink(Box<dyn Write>),
+}
+
+impl Output {
+    fn write(&mut self, text: &str) -> Result {
+        match self {
+            Output::Buffer(buffer) => buffer.push_str(text),
+            Output::Sink(sink) => sink.write_all(text.as_bytes()).map_err(|_| Error::OutputFailed)?,
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ArithmeticMode {
+    #[default]
+    Checked,
+    Wrapping,
+}
+
+impl ArithmeticMode {
+    fn unary<C: Cell>(self, a: C, checked: fn(C) -> Option<C>, wrapping: fn(C) -> C)
+        -> std::result::Result<C, Error> {
+        match self {
+            ArithmeticMode::Checked => checked(a).ok_or(Error::Overflow),
+            ArithmeticMode::Wrapping => Ok(wrapping(a)),
+        }
+    }
+
+    fn binary<C: Cell>(self, a: C, b: C,
+                       checked: fn(C, C) -> Option<C>,
+                       wrapping: fn(C, C) -> C) -> std::result::Result<C, Error> {
+        match self {
+            ArithmeticMode::Checked => checked(a, b).ok_or(Error::Overflow),
+            ArithmeticMode::Wrapping => Ok(wrapping(a, b)),
+        }
+    }
+
+    fn division<C: Cell>(self, a: C, b: C,
+                         checked: fn(C, C) -> Option<C>,
+                         wrapping: fn(C, C) -> C) -> std::result::Result<C, Error> {
+        if b == C::default() {
+            return Err(Error::DivisionByZero);
+        }
+        self.binary(a, b, checked, wrapping)
+    }
+
+    fn double<C: Cell>(self, a: i128, b: i128,
+                       checked: fn(i128, i128) -> Option<i128>,
+                       wrapping: fn(i128, i128) -> i128) -> std::result::Result<(C, C), Error> {
+        match self {
+            ArithmeticMode::Checked =>
+                checked(a, b).filter(|value| fits_double::<C>(*value)).map(split).ok_or(Error::Overflow),
+            ArithmeticMode::Wrapping => Ok(split(wrapping(a, b))),
+        }
+    }
+
+    fn narrow<C: Cell>(self, value: i128) -> std::result::Result<C, Error> {
+        match self {
+            ArithmeticMode::Checked => C::from_i128(value).ok_or(Error::Overflow),
+            ArithmeticMode::Wrapping => Ok(C::wrapping_from_i128(value)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ForthLimits {
+    pub max_instructions: u64,
+    pub max_stack_depth: usize,
+    pub max_call_depth: usize,
+    pub max_dictionary_size: usize,
+    pub max_definition_length: usize,
+    pub max_memory_cells: usize,
+    pub max_output_bytes: usize,
+}
+
+impl Default for ForthLimits {
+    fn default() -> Self {
+        ForthLimits {
+            max_instructions: u64::MAX,
+            max_stack_depth: 1 << 20,
+            max_call_depth: 1 << 20,
+            max_dictionary_size: 1 << 20,
+            max_definition_length: 1 << 16,
+            max_memory_cells: 1 << 20,
+            max_output_bytes: 1 << 24,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    Instructions,
+    StackDepth,
+    CallDepth,
+    DictionarySize,
+    DefinitionLength,
+    MemoryCells,
+    Output,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageError {
+    Io,
+    BadMagic,
+    UnsupportedVersion(u32),
+    Corrupt,
+    MissingNative,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IncludeError {
+    NotFound,
+    Cycle,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Control {
+    If(usize),
+    Else(usize),
+    Do(usize),
+    Begin(usize),
+    While(usize),
+}
+
+type TraceHook<C> = dyn FnMut(&TraceEvent<C>);
+
+pub struct Forth<C = Value> {
+    stack: Vec<C>,
+    #[cfg(feature = "float")]
+    float_stack: Vec<f64>,
+    loop_stack: Vec<(C, C)>,
+    return_stack: Vec<C>,
+    memory: Vec<C>,
+    dictionary_idx: HashMap<String, usize>,
+    dictionary: Vec<Rc<Entry<C>>>,
+    seq_id: usize,
+    compiling: Option<Definition<C>>,
+    strings: Vec<String>,
+    arithmetic_mode: ArithmeticMode,
+    limits: ForthLimits,
+    executed: u64,
+    written: usize,
+    nesting: usize,
+    trace: Vec<String>,
+    base: u32,
+    output: Output,
+    trace_hook: Option<Box<TraceHook<C>>>,
+    breakpoints: HashSet<String>,
+    session: Option<Session<C>>,
+    resolver: Option<Box<dyn SourceResolver>>,
+    included: HashSet<String>,
+    including: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Error {
+    DivisionByZero,
+    StackUnderflow,
+    UnknownWord,
+    InvalidWord,
+    ControlStructureMismatch,
+    Overflow,
+    InvalidAddress,
+    OutputFailed,
+    ReturnStackUnderflow,
+    ReturnStackImbalance,
+    LimitExceeded(Limit),
+    Thrown(i32),
+    Image(ImageError),
+    Include(IncludeError),
+    Located {
+        error: Box<Error>,
+        location: Box<ErrorLocation>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ErrorLocation {
+    pub file: Option<String>,
+    pub token: String,
+    pub span: Range<usize>,
+    pub line: String,
+    pub line_number: usize,
+    pub column: usize,
+    pub trace: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceEvent<C = Value> {
+    pub word: String,
+    pub depth: usize,
+    pub before: Vec<C>,
+    pub after: Vec<C>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Step {
+    Paused,
+    Breakpoint(String),
+    Finished,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Source {
+    pub name: String,
+    pub text: String,
+}
+
+// The returned name identifies the file for cycle detection, REQUIRE and error locations.
+pub trait SourceResolver {
+    fn resolve(&self, name: &str) -> Option<Source>;
+}
+
+pub struct DirectoryResolver {
+    root: PathBuf,
+}
+
+impl DirectoryResolver {
+    pub fn new(root: impl Into<PathBuf>) -> DirectoryResolver {
+        DirectoryResolver { root: root.into() }
+    }
+}
+
+impl SourceResolver for DirectoryResolver {
+    fn resolve(&self, name: &str) -> Option<Source> {
+        let root = self.root.canonicalize().ok()?;
+        // Canonicalizing first resolves `..` and symlinks, so nothing outside the root slips through.
+        let path = root.join(Path::new(name)).canonicalize().ok().filter(|path| path.starts_with(&root))?;
+        let text = std::fs::read_to_string(&path).ok()?;
+        let name = path.strip_prefix(&root).ok()?.to_string_lossy().into_owned();
+        Some(Source { name, text })
+    }
+}
+
+impl Error {
+    pub fn kind(&self) -> &Error {
+        match self {
+            Error::Located { error, .. } => error.kind(),
+            error => error,
+        }
+    }
+
+    pub fn location(&self) -> Option<&ErrorLocation> {
+        match self {
+            Error::Located { location, .. } => Some(location),
+            _ => None,
+        }
+    }
+
+    fn throw_code(&self) -> Option<i32> {
+        let code = match self.kind() {
+            Error::Thrown(code) => *code,
+            Error::StackUnderflow => -4,
+            Error::ReturnStackUnderflow => -6,
+            Error::InvalidAddress => -9,
+            Error::DivisionByZero => -10,
+            Error::Overflow => -11,
+            Error::UnknownWord => -13,
+            Error::ControlStructureMismatch => -22,
+            Error::ReturnStackImbalance => -25,
+            Error::InvalidWord => -32,
+            Error::OutputFailed => -57,
+            Error::LimitExceeded(Limit::StackDepth) => -3,
+            Error::LimitExceeded(Limit::CallDepth) => -5,
+            Error::LimitExceeded(Limit::DictionarySize | Limit::DefinitionLength | Limit::MemoryCells) => -8,
+            Error::Image(_) => -37,
+            Error::Include(_) => -38,
+            Error::LimitExceeded(Limit::Instructions | Limit::Output) | Error::Located { .. } => return None,
+        };
+        Some(code)
+    }
+}
+
+impl PartialEq for Error {
+    fn eq(&self, other: &Error) -> bool {
+        match (self.kind(), other.kind()) {
+            (Error::LimitExceeded(a), Error::LimitExceeded(b)) => a == b,
+            (Error::Thrown(a), Error::Thrown(b)) => a == b,
+            (Error::Image(a), Error::Image(b)) => a == b,
+            (Error::Include(a), Error::Include(b)) => a == b,
+            (a, b) => std::mem::discriminant(a) == std::mem::discriminant(b),
+        }
+    }
+}
+
+impl Eq for Error {}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::DivisionByZero => write!(f, "division by zero"),
+            Error::StackUnderflow => write!(f, "stack underflow"),
+            Error::UnknownWord => write!(f, "unknown word"),
+            Error::InvalidWord => write!(f, "invalid word"),
+            Error::ControlStructureMismatch => write!(f, "control structure mismatch"),
+            Error::Overflow => write!(f, "arithmetic overflow"),
+            Error::InvalidAddress => write!(f, "invalid address"),
+            Error::OutputFailed => write!(f, "output failed"),
+            Error::ReturnStackUnderflow => write!(f, "return stack underflow"),
+            Error::ReturnStackImbalance => write!(f, "return stack imbalance"),
+            Error::LimitExceeded(limit) => write!(f, "limit exceeded: {limit:?}"),
+            Error::Thrown(code) => write!(f, "uncaught throw {code}"),
+            Error::Image(ImageError::Io) => write!(f, "image could not be read or written"),
+            Error::Image(ImageError::BadMagic) => write!(f, "not a forth image"),
+            Error::Image(ImageError::UnsupportedVersion(version)) =>
+                write!(f, "unsupported image version {version}, expected {IMAGE_VERSION}"),
+            Error::Image(ImageError::Corrupt) => write!(f, "corrupt image"),
+            Error::Image(ImageError::MissingNative) =>
+                write!(f, "image needs a native word that is not defined"),
+            Error::Include(IncludeError::NotFound) => write!(f, "source file not found"),
+            Error::Include(IncludeError::Cycle) => write!(f, "source file includes itself"),
+            Error::Located { error, location } => {
+                let number = location.line_number.to_string();
+                let gutter = " ".repeat(number.len());
+                let indent = location.line[..location.column].chars().count();
+                let width = location.token.chars().count().max(1);
+                writeln!(f, "{error}: `{}`", location.token)?;
+                let file = location.file.as_ref().map_or(String::new(), |file| format!("{file}:"));
+                writeln!(f, "{gutter}--> {file}{}:{}", location.line_number, indent + 1)?;
+                writeln!(f, "{gutter} |")?;
+                writeln!(f, "{number} | {}", location.line)?;
+                write!(f, "{gutter} | {}{}", " ".repeat(indent), "^".repeat(width))?;
+                if !location.trace.is_empty() {
+                    write!(f, "\n{gutter} = in: {}", location.trace.join(" > "))?;
+                }
+                Ok(())
+            },
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl Forth {
+    pub fn new() -> Forth {
+        Forth::default()
+    }
+}
+
+impl<C: Cell> Default for Forth<C> {
+    fn default() -> Self {
+        let mut forth = Forth {
+            stack: Vec::new(),
+            #[cfg(feature = "float")]
+            float_stack: Vec::new(),
+            loop_stack: Vec::new(),
+            return_stack: Vec::new(),
+            memory: Vec::new(),
+            dictionary_idx: HashMap::new(),
+            dictionary: Vec::new(),
+            seq_id :0,
+            compiling: None,
+            strings: Vec::new(),
+            arithmetic_mode: ArithmeticMode::default(),
+            limits: ForthLimits::default(),
+            executed: 0,
+            written: 0,
+            nesting: 0,
+            trace: Vec::new(),
+            base: 10,
+            output: Output::Buffer(String::new()),
+            trace_hook: None,
+            breakpoints: HashSet::new(),
+            session: None,
+            resolver: None,
+            included: HashSet::new(),
+            including: Vec::new(),
+        };
+        for (name, op) in BASE_TOKENS {
+            forth.insert_word(name.to_string(), Word::Builtin(Instruction::Builtin(*op)), 0);
+        }
+        forth
+    }
+}
+
+impl<C: Cell> Forth<C> {
+    pub fn arithmetic_mode(&self) -> ArithmeticMode {
+        self.arithmetic_mode
+    }
+
+    pub fn set_arithmetic_mode(&mut self, mode: ArithmeticMode) {
+        self.arithmetic_mode = mode;
+    }
+
+    pub fn limits(&self) -> ForthLimits {
+        self.limits
+    }
+
+    pub fn set_limits(&mut self, limits: ForthLimits) {
+        self.limits = limits;
+    }
+
+    pub fn set_output<W: Write + 'static>(&mut self, sink: W) {
+        self.output = Output::Sink(Box::new(sink));
+    }
+
+    pub fn output(&self) -> &str {
+        match &self.output {
+            Output::Buffer(buffer) => buffer,
+            Output::Sink(_) => "",
+        }
+    }
+
+    pub fn take_output(&mut self) -> String {
+        match &mut self.output {
+            Output::Buffer(buffer) => std::mem::take(buffer),
+            Output::Sink(_) => String::new(),
+        }
+    }
+
+    pub fn stack(&self) -> &[C] {
+        &self.stack
+    }
+
+    #[cfg(feature = "float")]
+    pub fn float_stack(&self) -> &[f64] {
+        &self.float_stack
+    }
+
+    pub fn eval(&mut self, input: &str) -> Result {
+        self.executed = 0;
+        self.written = 0;
+        self.eval_source(input)
+    }
+
+    pub fn set_resolver<R: SourceResolver + 'static>(&mut self, resolver: R) {
+        self.resolver = Some(Box::new(resolver));
+    }
+
+    pub fn eval_file(&mut self, name: &str) -> Result {
+        self.executed = 0;
+        self.written = 0;
+        self.include(name, false)
+    }
+
+    fn include(&mut self, name: &str, once: bool) -> Result {
+        let source = self.resolver.as_ref().and_then(|resolver| resolver.resolve(name))
+            .ok_or(Error::Include(IncludeError::NotFound))?;
+        if self.including.contains(&source.name) {
+            return Err(Error::Include(IncludeError::Cycle));
+        }
+        if !self.included.insert(source.name.clone()) && once {
+            return Ok(());
+        }
+        self.including.push(source.name);
+        let result = self.eval_source(&source.text);
+        self.including.pop();
+        result
+    }
+
+    fn eval_source(&mut self, input: &str) -> Result {
+        let mut words = Tokens::new(input);
+        while let Some((start, el)) = words.next_spanned() {
+            // Character literals are the only case-sensitive tokens.
+            let word = if is_char_literal(el) { el.to_string() } else { el.to_ascii_uppercase() };
+            self.eval_word(&word, &mut words)
+                .map_err(|error| self.locate(error, input, start..start + el.len()))?;
+        }
+        Ok(())
+    }
+
+    fn eval_word(&mut self, word: &str, words: &mut Tokens) -> Result {
+        if word == "(" {
+            words.parse_until(')');
+            Ok(())
+        } else if word == "\\" {
+            words.skip_line();
+            Ok(())
+        } else if self.compiling.as_ref().is_some_and(|definition| !definition.suspended) {
+            self.compile(word, words)
+        } else {
+            self.count_instruction()?;
+            self.interpret(word, words)?;
+            self.check_stack_depth()
+        }
+    }
+
+    fn locate(&mut self, error: Error, input: &str, span: Range<usize>) -> Error {
+        // Errors from an included file already point into that file.
+        if error.location().is_some() {
+            return error;
+        }
+        let line_start = input[..span.start].rfind('\n').map_or(0, |idx| idx + 1);
+        let line_end = input[span.start..].find('\n').map_or(input.len(), |idx| span.start + idx);
+        let location = ErrorLocation {
+            file: self.including.last().cloned(),
+            token: input[span.clone()].to_string(),
+            line: input[line_start..line_end].trim_end_matches('\r').to_string(),
+            line_number: input[..span.start].matches('\n').count() + 1,
+            column: span.start - line_start,
+            span,
+            trace: std::mem::take(&mut self.trace),
+        };
+        Error::Located { error: Box::new(error), location: Box::new(location) }
+    }
+
+    // Output is counted per `eval`, so a buffer that is never drained still grows one bounded step at a time.
+    fn write(&mut self, text: &str) -> Result {
+        let written = self.written + text.len();
+        if written > self.limits.max_output_bytes {
+            return Err(Error::LimitExceeded(Limit::Output));
+        }
+        self.written = written;
+        self.output.write(text)
+    }
+
+    fn count_instruction(&mut self) -> Result {
+        self.executed += 1;
+        if self.executed > self.limits.max_instructions {
+            return Err(Error::LimitExceeded(Limit::Instructions));
+        }
+        Ok(())
+    }
+
+    fn check_stack_depth(&self) -> Result {
+        let depth = self.stack.len().max(self.return_stack.len());
+        #[cfg(feature = "float")]
+        let depth = depth.max(self.float_stack.len());
+        if depth > self.limits.max_stack_depth {
+            return Err(Error::LimitExceeded(Limit::StackDepth));
+        }
+        Ok(())
+    }
+
+    pub fn eval_atomic(&mut self, input: &str) -> Result {
+        let snapshot = self.snapshot();
+        let result = self.eval(input);
+        if result.is_err() {
+            self.restore(snapshot);
+        }
+        result
+    }
+
+    pub fn set_trace_hook<F>(&mut self, hook: F)
+    where F: FnMut(&TraceEvent<C>) + 'static {
+        self.trace_hook = Some(Box::new(hook));
+    }
+
+    pub fn clear_trace_hook(&mut self) {
+        self.trace_hook = None;
+    }
+
+    pub fn set_breakpoint(&mut self, name: &str) -> Result {
+        let idx = self.find_word(Some(name))?;
+        if !matches!(self.dictionary[idx].word, Word::Colon(_)) {
+            return Err(Error::InvalidWord);
+        }
+        self.breakpoints.insert(self.dictionary[idx].name.clone());
+        Ok(())
+    }
+
+    pub fn clear_breakpoint(&mut self, name: &str) -> bool {
+        self.breakpoints.remove(&name.to_ascii_uppercase())
+    }
+
+    pub fn start(&mut self, input: &str) {
+        self.executed = 0;
+        self.written = 0;
+        self.session = Some(Session {
+            input: input.to_string(),
+            position: 0,
+            span: 0..0,
+            frames: Vec::new(),
+            loop_depth: self.loop_stack.len(),
+            return_depth: self.return_stack.len(),
+        });
+    }
+
+    pub fn is_stepping(&self) -> bool {
+        self.session.is_some()
+    }
+
+    pub fn step(&mut self) -> std::result::Result<Step, Error> {
+        let Some(mut session) = self.session.take() else {
+            return Ok(Step::Finished);
+        };
+        let result = self.step_session(&mut session);
+        if result.is_err() || session.frames.is_empty() {
+            self.loop_stack.truncate(session.loop_depth);
+            self.return_stack.truncate(session.return_depth);
+        }
+        let finished = session.frames.is_empty()
+            && Tokens { input: &session.input, position: session.position }.next_spanned().is_none();
+        match result {
+            Err(error) => Err(self.locate(error, &session.input, session.span)),
+            Ok(Some(name)) => {
+                self.session = Some(session);
+                Ok(Step::Breakpoint(name))
+            },
+            Ok(None) if finished => Ok(Step::Finished),
+            Ok(None) => {
+                self.session = Some(session);
+                Ok(Step::Paused)
+            },
+        }
+    }
+
+    pub fn resume(&mut self) -> std::result::Result<Step, Error> {
+        loop {
+            match self.step()? {
+                Step::Paused => {},
+                step => return Ok(step),
+            }
+        }
+    }
+
+    // Runs one instruction of the innermost word, or the next token once every word has returned.
+    fn step_session(&mut self, session: &mut Session<C>) -> std::result::Result<Option<String>, Error> {
+        let depth = session.frames.len();
+        let result = if session.frames.is_empty() {
+            let mut words = Tokens { input: &session.input, position: session.position };
+            let Some((start, el)) = words.next_spanned() else {
+                return Ok(None);
+            };
+            session.span = start..start + el.len();
+            let word = if is_char_literal(el) { el.to_string() } else { el.to_ascii_uppercase() };
+            let interpreting = self.compiling.as_ref().is_none_or(|definition| definition.suspended);
+            let result = match self.dictionary_idx.get(&word) {
+                Some(&idx) if interpreting => self.count_instruction()
+                    .and_then(|()| self.dispatch(self.compile_entry(idx), &mut session.frames))
+                    .and_then(|()| self.check_stack_depth()),
+                _ => self.eval_word(&word, &mut words),
+            };
+            session.position = words.position;
+            result
+        } else {
+            self.step_frame(&mut session.frames)
+        };
+        if let Err(error) = result {
+            self.throw(&mut session.frames, error)?;
+        }
+        let entered = session.frames.last().filter(|_| session.frames.len() > depth);
+        Ok(entered.map(|frame| self.dictionary[frame.word].name.clone())
+            .filter(|name| self.breakpoints.contains(name)))
+    }
+
+    fn snapshot(&self) -> Snapshot<C> {
+        Snapshot {
+            stack: self.stack.clone(),
+            memory: self.memory.clone(),
+            dictionary_idx: self.dictionary_idx.clone(),
+            dictionary: self.dictionary.clone(),
+            strings_len: self.strings.len(),
+            compiling: self.compiling.clone(),
+            base: self.base,
+            included: self.included.clone(),
+            #[cfg(feature = "float")]
+            float_stack: self.float_stack.clone(),
+        }
+    }
+
+    fn restore(&mut self, snapshot: Snapshot<C>) {
+        self.stack = snapshot.stack;
+        self.memory = snapshot.memory;
+        self.dictionary_idx = snapshot.dictionary_idx;
+        self.dictionary = snapshot.dictionary;
+        self.seq_id = self.dictionary.len();
+        self.strings.truncate(snapshot.strings_len);
+        self.compiling = snapshot.compiling;
+        self.base = snapshot.base;
+        self.included = snapshot.included;
+        #[cfg(feature = "float")]
+        {
+            self.float_stack = snapshot.float_stack;
+        }
+    }
+
+    pub fn is_compiling(&self) -> bool {
+        self.compiling.is_some()
+    }
+
+    pub fn memory(&self) -> &[C] {
+        &self.memory
+    }
+
+    pub fn save_image(&self, writer: &mut impl Write) -> Result {
+        self.write_image(writer, None)
+    }
+
+    pub fn save_image_with_stack(&self, writer: &mut impl Write) -> Result {
+        self.write_image(writer, Some(&self.stack))
+    }
+
+    fn write_image(&self, writer: &mut impl Write, stack: Option<&[C]>) -> Result {
+        let mut out = IMAGE_MAGIC.to_vec();
+        out.extend_from_slice(&IMAGE_VERSION.to_le_bytes());
+        encode_u32(&mut out, BASE_TOKENS.len());
+        encode_u32(&mut out, self.strings.len());
+        for text in &self.strings {
+            encode_str(&mut out, text);
+        }
+        encode_u32(&mut out, self.dictionary.len() - BASE_TOKENS.len());
+        for entry in &self.dictionary[BASE_TOKENS.len()..] {
+            encode_str(&mut out, &entry.name);
+            encode_u32(&mut out, entry.here);
+            out.push(entry.immediate as u8);
+            let defined_by = entry.defined_by.and_then(|word| DEFINING_WORDS.iter().position(|w| *w == word));
+            out.push(defined_by.map_or(0, |idx| idx + 1) as u8);
+            match &entry.word {
+                Word::Colon(instructions) => {
+                    out.push(0);
+                    encode_u32(&mut out, instructions.len());
+                    for instruction in instructions {
+                        instruction.encode(&mut out);
+                    }
+                },
+                Word::Builtin(_) => out.push(1),
+                Word::Native(_, native) => {
+                    out.push(2);
+                    encode_u32(&mut out, native.arity);
+                },
+            }
+        }
+        for values in [Some(&self.memory[..]), stack] {
+            let Some(values) = values else {
+                out.push(0);
+                continue;
+            };
+            out.push(1);
+            encode_u32(&mut out, values.len());
+            for value in values {
+                out.extend_from_slice(&value.to_i128().to_le_bytes());
+            }
+        }
+        writer.write_all(&out).map_err(|_| Error::Image(ImageError::Io))
+    }
+
+    pub fn load_image(&mut self, reader: &mut impl Read) -> Result {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).map_err(|_| Error::Image(ImageError::Io))?;
+        if !bytes.starts_with(IMAGE_MAGIC) {
+            return Err(Error::Image(ImageError::BadMagic));
+        }
+        let mut image = ImageReader { bytes: &bytes, position: IMAGE_MAGIC.len() };
+        let version = image.u32()?;
+        if version != IMAGE_VERSION {
+            return Err(Error::Image(ImageError::UnsupportedVersion(version)));
+        }
+        if image.len()? != BASE_TOKENS.len() {
+            return Err(Error::Image(ImageError::Corrupt));
+        }
+        let mut strings = Vec::new();
+        for _ in 0..image.len()? {
+            strings.push(image.str()?);
+        }
+
+        let mut dictionary = self.dictionary[..BASE_TOKENS.len()].to_vec();
+        for _ in 0..image.len()? {
+            let name = image.str()?;
+            let here = image.len()?;
+            let immediate = match image.u8()? {
+                0 => false,
+                1 => true,
+                _ => return Err(Error::Image(ImageError::Corrupt)),
+            };
+            let defined_by = match image.u8()? as usize {
+                0 => None,
+                idx if idx <= DEFINING_WORDS.len() => Some(DEFINING_WORDS[idx - 1]),
+                _ => return Err(Error::Image(ImageError::Corrupt)),
+            };
+            let idx = dictionary.len();
+            let word = match image.u8()? {
+                0 => {
+                    let length = image.len()?;
+                    let mut instructions = Vec::new();
+                    for _ in 0..length {
+                        instructions.push(image.instruction(&dictionary, strings.len(), length)?);
+                    }
+                    Word::Colon(instructions)
+                },
+                1 => Word::Builtin(Instruction::Forget(idx)),
+                2 => {
+                    let arity = image.len()?;
+                    // Host closures cannot be stored, so they are taken from this instance by name.
+                    let function = self.dictionary.iter().rev()
+                        .find_map(|entry| match &entry.word {
+                            Word::Native(_, native) if entry.name == name => Some(Rc::clone(&native.function)),
+                            _ => None,
+                        })
+                        .ok_or(Error::Image(ImageError::MissingNative))?;
+                    Word::Native(Instruction::Native(idx), Native { arity, function })
+                },
+                _ => return Err(Error::Image(ImageError::Corrupt)),
+            };
+            dictionary.push(Rc::new(Entry { name, word, here, immediate, defined_by }));
+        }
+
+        let mut sections = Vec::new();
+        for _ in 0..2 {
+            sections.push(match image.u8()? {
+                0 => None,
+                1 => Some(image.values()?),
+                _ => return Err(Error::Image(ImageError::Corrupt)),
+            });
+        }
+        if image.position != bytes.len() {
+            return Err(Error::Image(ImageError::Corrupt));
+        }
+        let stack = sections.pop().unwrap();
+        let memory = sections.pop().unwrap().ok_or(Error::Image(ImageError::Corrupt))?;
+
+        self.dictionary_idx = dictionary.iter().enumerate()
+            .map(|(idx, entry)| (entry.name.clone(), idx))
+            .collect();
+        self.seq_id = dictionary.len();
+        self.dictionary = dictionary;
+        self.strings = strings;
+        self.memory = memory;
+        self.stack = stack.unwrap_or_default();
+        self.loop_stack.clear();
+        self.return_stack.clear();
+        self.compiling = None;
+        self.session = None;
+        Ok(())
+    }
+
+    pub fn words(&self) -> Vec<String> {
+        let mut words: Vec<(usize, String)> = self.dictionary_idx.iter()
+            .map(|(name, &idx)| (idx, name.clone()))
+            .collect();
+        words.sort_unstable_by_key(|(idx, _)| std::cmp::Reverse(*idx));
+        words.into_iter().map(|(_, name)| name).collect()
+    }
 
-#[derive(Debug, PartialEq, Eq)]
-pub enum Error {
-    DivisionByZero,
-    StackUnderflow,
-    UnknownWord,
-    InvalidWord,
-}
+    pub fn definition(&self, name: &str) -> Option<String> {
+        let xt = self.find_word(Some(name)).ok()?;
+        Some(self.decompile(xt))
+    }
 
-impl Forth {
-    pub fn new() -> Forth {
-        Forth {
-            stack: Vec::new(),
-            user_operations_idx: HashMap::new(),
-            user_operations: Vec::new(),
-            seq_id :0
+    fn forget(&mut self, idx: usize) -> Result {
+        let stepping = self.session.as_ref().is_some_and(|session| !session.frames.is_empty());
+        // An open definition may already hold calls into the words that would be dropped.
+        if idx < BASE_TOKENS.len() || stepping || self.compiling.is_some() {
+            return Err(Error::InvalidWord);
         }
+        self.memory.truncate(self.dictionary[idx].here);
+        self.dictionary.truncate(idx);
+        self.seq_id = idx;
+        self.dictionary_idx = self.dictionary.iter().enumerate()
+            .map(|(idx, entry)| (entry.name.clone(), idx))
+            .collect();
+        Ok(())
     }
 
-    pub fn stack(&self) -> &[Value] {
-        &self.stack
+    fn decompile(&self, idx: usize) -> String {
+        let entry = &self.dictionary[idx];
+        let instructions = match &entry.word {
+            Word::Colon(instructions) => instructions,
+            Word::Builtin(Instruction::Forget(_)) => return format!("MARKER {}", entry.name),
+            Word::Builtin(_) | Word::Native(..) => return format!("CODE {}", entry.name),
+        };
+        // Data words are shown as the defining word that made them, with any value it took.
+        if let Some(defining_word) = entry.defined_by {
+            let mut text = String::new();
+            if defining_word.ends_with("CONSTANT") {
+                for instruction in instructions {
+                    text.push_str(&format!("{} ", self.instruction_name(*instruction)));
+                }
+            }
+            text.push_str(&format!("{defining_word} {}", entry.name));
+            if entry.immediate {
+                text.push_str(" IMMEDIATE");
+            }
+            return text;
+        }
+        // Control words leave no trace of their own, so recover them from the branch targets.
+        let mut before: Vec<Vec<&str>> = vec![Vec::new(); instructions.len() + 1];
+        let mut begins: Vec<usize> = vec![0; instructions.len() + 1];
+        let mut at: Vec<Option<&str>> = vec![None; instructions.len()];
+        for (pc, instruction) in instructions.iter().enumerate() {
+            match *instruction {
+                Instruction::BranchIfZero(target) if target <= pc => {
+                    begins[target] += 1;
+                    at[pc] = Some("UNTIL");
+                },
+                Instruction::BranchIfZero(target) => match instructions[target - 1] {
+                    Instruction::Branch(start) if start <= pc => {
+                        begins[start] += 1;
+                        at[pc] = Some("WHILE");
+                        at[target - 1] = Some("REPEAT");
+                    },
+                    Instruction::Branch(end) if end >= target => {
+                        at[pc] = Some("IF");
+                        at[target - 1] = Some("ELSE");
+                        before[end].insert(0, "THEN");
+                    },
+                    _ => {
+                        at[pc] = Some("IF");
+                        before[target].insert(0, "THEN");
+                    },
+                },
+                _ => {},
+            }
+        }
+        let mut text = format!(": {}", entry.name);
+        for (pc, words) in before.iter().enumerate() {
+            for word in words.iter().copied().chain(std::iter::repeat_n("BEGIN", begins[pc])) {
+                text.push(' ');
+                text.push_str(word);
+            }
+            let Some(instruction) = instructions.get(pc) else {
+                break;
+            };
+            text.push(' ');
+            match (at[pc], *instruction) {
+                (Some(word), _) => text.push_str(word),
+                (None, instruction) => text.push_str(&self.instruction_name(instruction)),
+            }
+        }
+        text.push_str(" ;");
+        if entry.immediate {
+            text.push_str(" IMMEDIATE");
+        }
+        text
     }
 
-    pub fn eval(&mut self, input: &str) -> Result {
-        if input.starts_with(':') {
-            return self.add_user_operation(input);
+    fn instruction_name(&self, instruction: Instruction<C>) -> String {
+        match instruction {
+            Instruction::Push(value) => value.format(10),
+            Instruction::Builtin(op) => {
+                let (name, _) = BASE_TOKENS.iter().find(|(_, builtin)| *builtin == op).unwrap();
+                name.to_string()
+            },
+            Instruction::Call(idx) | Instruction::Native(idx) | Instruction::Forget(idx) =>
+                self.dictionary[idx].name.clone(),
+            Instruction::CompileWord(idx) => format!("POSTPONE {}", self.dictionary[idx].name),
+            Instruction::CompileToken(idx) => format!("POSTPONE {}", self.strings[idx]),
+            Instruction::PushXt(idx) => format!("['] {}", self.dictionary[idx].name),
+            Instruction::PushString(_, idx) => format!("S\" {}\"", self.strings[idx]),
+            Instruction::Print(idx) => format!(".\" {}\"", self.strings[idx]),
+            Instruction::Do => "DO".to_string(),
+            Instruction::Loop(_) => "LOOP".to_string(),
+            Instruction::PlusLoop(_) => "+LOOP".to_string(),
+            Instruction::LoopIndex(depth) => if depth == 0 { "I" } else { "J" }.to_string(),
+            Instruction::ToR => ">R".to_string(),
+            Instruction::FromR => "R>".to_string(),
+            Instruction::RFetch => "R@".to_string(),
+            Instruction::Exit => "EXIT".to_string(),
+            Instruction::Recurse => "RECURSE".to_string(),
+            Instruction::Branch(_) => "BRANCH".to_string(),
+            Instruction::BranchIfZero(_) => "0BRANCH".to_string(),
+            #[cfg(feature = "float")]
+            Instruction::FPush(bits) => format!("{:E}", f64::from_bits(bits)),
+        }
+    }
+
+    fn interpret(&mut self, word: &str, words: &mut Tokens) -> Result {
+        if self.dictionary_idx.contains_key(word) {
+            let instruction = self.compile_word(word)?;
+            return self.run(instruction);
         }
-        for el in input.split_whitespace() {
-            if let Ok(number) = el.parse::<Value>() {
+        match word {
+            ":" if self.compiling.is_some() => Err(Error::ControlStructureMismatch),
+            ":" => self.start_user_operation(words),
+            "]" => {
+                let definition = self.compiling.as_mut().ok_or(Error::ControlStructureMismatch)?;
+                definition.suspended = false;
+                Ok(())
+            },
+            "'" => {
+                let xt = self.find_word(words.next())?;
+                self.stack.push(cell(xt));
+                Ok(())
+            },
+            ".\"" => self.write(words.parse_until('"')),
+            "S\"" => {
+                let (address, length) = self.store_string(words.parse_until('"'))?;
+                self.stack.extend([address, length]);
+                Ok(())
+            },
+            "CHAR" => {
+                let ch = words.next().and_then(|word| word.chars().next()).ok_or(Error::InvalidWord)?;
+                self.stack.push(cell(ch as usize));
+                Ok(())
+            },
+            "SEE" => {
+                let xt = self.find_word(words.next())?;
+                let text = self.decompile(xt);
+                self.write(&format!("{text}\n"))
+            },
+            "FORGET" => {
+                let xt = self.find_word(words.next())?;
+                self.forget(xt)
+            },
+            "INCLUDE" | "REQUIRE" => {
+                let name = words.next().ok_or(Error::InvalidWord)?;
+                self.include(name, word == "REQUIRE")
+            },
+            _ if DEFINING_WORDS.contains(&word) => {
+                let key = definition_name::<C>(words.next())?;
+                self.add_data_operation(word, key)
+            },
+            _ => match self.parse_double(word) {
+                Some((low, high)) => {
+                    self.stack.extend([low, high]);
+                    Ok(())
+                },
+                None => {
+                    let instruction = self.compile_word(word)?;
+                    self.run(instruction)
+                },
+            },
+        }
+    }
+
+    fn find_word(&self, word: Option<&str>) -> std::result::Result<usize, Error> {
+        let word = word.ok_or(Error::InvalidWord)?.to_ascii_uppercase();
+        self.dictionary_idx.get(&word).copied().ok_or(Error::UnknownWord)
+    }
+
+    fn compile_word(&self, word: &str) -> std::result::Result<Instruction<C>, Error> {
+        if let Some(&idx) = self.dictionary_idx.get(word) {
+            Ok(self.compile_entry(idx))
+        } else if let Some(instruction) = self.literal(word) {
+            Ok(instruction)
+        } else if CONTROL_WORDS.contains(&word) {
+            Err(Error::ControlStructureMismatch)
+        } else if DEFINING_WORDS.contains(&word) || TOP_LEVEL_WORDS.contains(&word) {
+            Err(Error::InvalidWord)
+        } else {
+            Err(Error::UnknownWord)
+        }
+    }
+
+    fn compile_entry(&self, idx: usize) -> Instruction<C> {
+        match self.dictionary[idx].word {
+            Word::Builtin(instruction) | Word::Native(instruction, _) => instruction,
+            Word::Colon(_) => Instruction::Call(idx),
+        }
+    }
+
+    fn exec_instruction(&mut self, instruction: Instruction<C>) -> Result {
+        match instruction {
+            Instruction::Push(number) => {
                 self.stack.push(number);
-            } else {
-                self.try_exec_operation(el)?;
-            }
+                Ok(())
+            },
+            Instruction::Builtin(op) => self.try_exec_operation(op),
+            Instruction::LoopIndex(depth) => {
+                let idx = self.loop_stack.len().checked_sub(depth + 1)
+                    .ok_or(Error::ControlStructureMismatch)?;
+                self.stack.push(self.loop_stack[idx].0);
+                Ok(())
+            },
+            Instruction::Do => {
+                let index = self.stack.pop().ok_or(Error::StackUnderflow)?;
+                let Some(limit) = self.stack.pop() else {
+                    self.stack.push(index);
+                    return Err(Error::StackUnderflow);
+                };
+                self.loop_stack.push((index, limit));
+                Ok(())
+            },
+            Instruction::Print(idx) => {
+                let text = self.strings[idx].clone();
+                self.write(&text)
+            },
+            Instruction::PushXt(idx) => {
+                self.stack.push(cell(idx));
+                Ok(())
+            },
+            Instruction::PushString(address, idx) => {
+                let length = self.strings[idx].chars().count();
+                self.stack.extend([cell::<C>(address), cell(length)]);
+                Ok(())
+            },
+            #[cfg(feature = "float")]
+            Instruction::FPush(bits) => {
+                self.float_stack.push(f64::from_bits(bits));
+                Ok(())
+            },
+            Instruction::Native(idx) => self.call_native(idx),
+            Instruction::CompileWord(idx) => {
+                let instruction = self.compile_entry(idx);
+                let definition = self.compiling.as_mut().ok_or(Error::ControlStructureMismatch)?;
+                definition.instructions.push(instruction);
+                if definition.instructions.len() > self.limits.max_definition_length {
+                    return Err(Error::LimitExceeded(Limit::DefinitionLength));
+                }
+                Ok(())
+            },
+            Instruction::CompileToken(idx) => {
+                if self.compiling.is_none() {
+                    return Err(Error::ControlStructureMismatch);
+                }
+                let token = self.strings[idx].clone();
+                self.compile(&token, &mut Tokens::new(""))
+            },
+            _ => Err(Error::ControlStructureMismatch),
         }
-        Ok(())
     }
 
-    fn try_exec_operation(&mut self, el: &str) -> Result {
+    fn try_exec_operation(&mut self, op: Builtin) -> Result {
         let length = self.stack.len();
-        let operation_name_upper = el.to_ascii_uppercase();
-        let operation_name = operation_name_upper.as_str();
-
-        let min_length_need = match operation_name {
-            _ if operation_name.starts_with(IDX_PREFIX) =>
-                return self.eval_internal_operation(operation_name),
-            _ if self.user_operations_idx.contains_key(operation_name) =>
-                return self.eval_user_operation(operation_name),
-            "+" | "-" | "*" | "/" | "OVER" | "SWAP" => 2,
-            "DUP" | "DROP" => 1,
-            _ => return Err(Error::UnknownWord)
+        let mode = self.arithmetic_mode;
+        match op {
+            Builtin::Add => self.binary_operation(|a, b|
+                mode.binary(a, b, C::checked_add, C::wrapping_add))?,
+            Builtin::Sub => self.binary_operation(|a, b|
+                mode.binary(a, b, C::checked_sub, C::wrapping_sub))?,
+            Builtin::Mul => self.binary_operation(|a, b|
+                mode.binary(a, b, C::checked_mul, C::wrapping_mul))?,
+            Builtin::Div => self.binary_operation(|a, b|
+                mode.division(a, b, C::checked_div, C::wrapping_div))?,
+            Builtin::Mod => self.binary_operation(|a, b|
+                mode.division(a, b, C::checked_rem, C::wrapping_rem))?,
+            Builtin::Eq => self.binary_operation(|a, b| Ok(flag(a == b)))?,
+            Builtin::Lt => self.binary_operation(|a, b| Ok(flag(a < b)))?,
+            Builtin::Gt => self.binary_operation(|a, b| Ok(flag(a > b)))?,
+            Builtin::Ne => self.binary_operation(|a, b| Ok(flag(a != b)))?,
+            Builtin::And => self.binary_operation(|a, b| Ok(a & b))?,
+            Builtin::Or => self.binary_operation(|a, b| Ok(a | b))?,
+            Builtin::Xor => self.binary_operation(|a, b| Ok(a ^ b))?,
+            Builtin::Min => self.binary_operation(|a, b| Ok(a.min(b)))?,
+            Builtin::Max => self.binary_operation(|a, b| Ok(a.max(b)))?,
+            Builtin::ZeroEq => self.unary_operation(|a| Ok(flag(a == C::default())))?,
+            Builtin::ZeroLt => self.unary_operation(|a| Ok(flag(a < C::default())))?,
+            Builtin::Invert => self.unary_operation(|a| Ok(!a))?,
+            Builtin::Negate => self.unary_operation(|a|
+                mode.unary(a, C::checked_neg, C::wrapping_neg))?,
+            Builtin::Abs => self.unary_operation(|a|
+                mode.unary(a, C::checked_abs, C::wrapping_abs))?,
+            Builtin::DivMod => {
+                self.require(2)?;
+                let (a, b) = (self.stack[length - 2], self.stack[length - 1]);
+                let remainder = mode.division(a, b, C::checked_rem, C::wrapping_rem)?;
+                let quotient = mode.division(a, b, C::checked_div, C::wrapping_div)?;
+                self.stack[length - 2] = remainder;
+                self.stack[length - 1] = quotient;
+            },
+            Builtin::MulDiv => {
+                self.require(3)?;
+                let (a, b, c) = (self.stack[length - 3], self.stack[length - 2], self.stack[length - 1]);
+                if c == C::default() {
+                    return Err(Error::DivisionByZero);
+                }
+                let product = mode.binary(a.to_i128(), b.to_i128(), i128::checked_mul, i128::wrapping_mul)?;
+                let result = mode.narrow(mode.division(product, c.to_i128(), i128::checked_div, i128::wrapping_div)?)?;
+                self.stack.truncate(length - 3);
+                self.stack.push(result);
+            },
+            Builtin::DAdd | Builtin::DSub => {
+                self.require(4)?;
+                let a = join(self.stack[length - 4], self.stack[length - 3])?;
+                let b = join(self.stack[length - 2], self.stack[length - 1])?;
+                let (low, high): (C, C) = if op == Builtin::DAdd {
+                    mode.double(a, b, i128::checked_add, i128::wrapping_add)?
+                } else {
+                    mode.double(a, b, i128::checked_sub, i128::wrapping_sub)?
+                };
+                self.stack.truncate(length - 4);
+                self.stack.extend([low, high]);
+            },
+            Builtin::DNegate => {
+                self.require(2)?;
+                let value = join(self.stack[length - 2], self.stack[length - 1])?;
+                let (low, high) = mode.double(0, value, i128::checked_sub, i128::wrapping_sub)?;
+                self.stack[length - 2] = low;
+                self.stack[length - 1] = high;
+            },
+            Builtin::DDot => {
+                self.require(2)?;
+                let value = join(self.stack[length - 2], self.stack[length - 1])?;
+                self.write(&format!("{} ", value.format(self.base)))?;
+                self.stack.truncate(length - 2);
+            },
+            Builtin::MMul => {
+                self.require(2)?;
+                let (a, b) = (signed(self.stack[length - 2]), signed(self.stack[length - 1]));
+                let (low, high) = mode.double(a, b, i128::checked_mul, i128::wrapping_mul)?;
+                self.stack[length - 2] = low;
+                self.stack[length - 1] = high;
+            },
+            Builtin::UMMul => {
+                self.require(2)?;
+                let product = unsigned(self.stack[length - 2]).checked_mul(unsigned(self.stack[length - 1]))
+                    .ok_or(Error::Overflow)?;
+                let high = if C::BITS == 128 { 0 } else { product >> C::BITS };
+                self.stack[length - 2] = C::wrapping_from_i128(product as i128);
+                self.stack[length - 1] = C::wrapping_from_i128(high as i128);
+            },
+            Builtin::UMDivMod => {
+                self.require(3)?;
+                let dividend = join_unsigned(self.stack[length - 3], self.stack[length - 2])?;
+                let divisor = unsigned(self.stack[length - 1]);
+                if divisor == 0 {
+                    return Err(Error::DivisionByZero);
+                }
+                let quotient = dividend / divisor;
+                if quotient > unsigned(!C::default()) {
+                    return Err(Error::Overflow);
+                }
+                self.stack.truncate(length - 3);
+                self.stack.extend([(dividend % divisor) as i128, quotient as i128].map(C::wrapping_from_i128));
+            },
+            Builtin::SMDivRem | Builtin::FMDivMod => {
+                self.require(3)?;
+                let dividend = join(self.stack[length - 3], self.stack[length - 2])?;
+                let divisor = signed(self.stack[length - 1]);
+                if divisor == 0 {
+                    return Err(Error::DivisionByZero);
+                }
+                let mut quotient = dividend.checked_div(divisor).ok_or(Error::Overflow)?;
+                let mut remainder = dividend % divisor;
+                // Floored division rounds toward negative infinity, so the remainder takes the divisor's sign.
+                if op == Builtin::FMDivMod && remainder != 0 && (remainder < 0) != (divisor < 0) {
+                    quotient -= 1;
+                    remainder += divisor;
+                }
+                if signed(C::wrapping_from_i128(quotient)) != quotient {
+                    return Err(Error::Overflow);
+                }
+                self.stack.truncate(length - 3);
+                self.stack.extend([remainder, quotient].map(C::wrapping_from_i128));
+            },
+            Builtin::TwoFetch => {
+                self.require(1)?;
+                let address = self.address(self.stack[length - 1])?;
+                self.address(cell(address + 1))?;
+                self.stack[length - 1] = self.memory[address + 1];
+                self.stack.push(self.memory[address]);
+            },
+            Builtin::TwoStore => {
+                self.require(3)?;
+                let address = self.address(self.stack[length - 1])?;
+                self.address(cell(address + 1))?;
+                self.memory[address] = self.stack[length - 2];
+                self.memory[address + 1] = self.stack[length - 3];
+                self.stack.truncate(length - 3);
+            },
+            Builtin::Dup => {
+                self.require(1)?;
+                self.stack.push(self.stack[length - 1]);
+            },
+            Builtin::Over => {
+                self.require(2)?;
+                self.stack.push(self.stack[length - 2]);
+            },
+            Builtin::Drop => {
+                self.require(1)?;
+                self.stack.pop();
+            },
+            Builtin::Swap => {
+                self.require(2)?;
+                self.stack.swap(length - 2, length - 1);
+            },
+            Builtin::Rot => {
+                self.require(3)?;
+                self.stack[length - 3..].rotate_left(1);
+            },
+            Builtin::MinusRot => {
+                self.require(3)?;
+                self.stack[length - 3..].rotate_right(1);
+            },
+            Builtin::Nip => {
+                self.require(2)?;
+                self.stack.remove(length - 2);
+            },
+            Builtin::Tuck => {
+                self.require(2)?;
+                self.stack.insert(length - 2, self.stack[length - 1]);
+            },
+            Builtin::TwoDup => {
+                self.require(2)?;
+                self.stack.extend_from_within(length - 2..);
+            },
+            Builtin::TwoDrop => {
+                self.require(2)?;
+                self.stack.truncate(length - 2);
+            },
+            Builtin::TwoSwap => {
+                self.require(4)?;
+                self.stack[length - 4..].rotate_left(2);
+            },
+            Builtin::TwoOver => {
+                self.require(4)?;
+                self.stack.extend_from_within(length - 4..length - 2);
+            },
+            Builtin::QuestionDup => {
+                self.require(1)?;
+                if self.stack[length - 1] != C::default() {
+                    self.stack.push(self.stack[length - 1]);
+                }
+            },
+            Builtin::Depth => self.stack.push(cell(length)),
+            Builtin::Pick | Builtin::Roll => {
+                self.require(1)?;
+                let depth = usize::try_from(self.stack[length - 1].to_i128())
+                    .map_err(|_| Error::StackUnderflow)?;
+                self.require(depth.checked_add(2).ok_or(Error::StackUnderflow)?)?;
+                self.stack.pop();
+                let idx = length - 2 - depth;
+                let value = if op == Builtin::Pick {
+                    self.stack[idx]
+                } else {
+                    self.stack.remove(idx)
+                };
+                self.stack.push(value);
+            },
+            Builtin::Fetch => {
+                self.require(1)?;
+                self.stack[length - 1] = self.fetch(self.stack[length - 1])?;
+            },
+            Builtin::Store => {
+                self.require(2)?;
+                self.store(self.stack[length - 1], self.stack[length - 2])?;
+                self.stack.truncate(length - 2);
+            },
+            Builtin::PlusStore => {
+                self.require(2)?;
+                let address = self.stack[length - 1];
+                let sum = mode.binary(self.fetch(address)?, self.stack[length - 2],
+                                      C::checked_add, C::wrapping_add)?;
+                self.store(address, sum)?;
+                self.stack.truncate(length - 2);
+            },
+            Builtin::Comma => {
+                self.require(1)?;
+                self.allot(cell(1))?;
+                let here = self.memory.len();
+                self.memory[here - 1] = self.stack[length - 1];
+                self.stack.pop();
+            },
+            Builtin::Allot => {
+                self.require(1)?;
+                self.allot(self.stack[length - 1])?;
+                self.stack.pop();
+            },
+            Builtin::Here => self.stack.push(cell(self.memory.len())),
+            Builtin::Dot => {
+                self.require(1)?;
+                self.write(&format!("{} ", self.stack[length - 1].format(self.base)))?;
+                self.stack.pop();
+            },
+            Builtin::Type => {
+                self.require(2)?;
+                let (address, count) = (self.stack[length - 2], self.stack[length - 1].to_i128());
+                let text: String = if count <= 0 {
+                    String::new()
+                } else {
+                    let start = self.address(address)?;
+                    let end = usize::try_from(count - 1).ok().and_then(|offset| start.checked_add(offset))
+                        .filter(|end| *end < self.memory.len())
+                        .ok_or(Error::InvalidAddress)?;
+                    self.memory[start..=end].iter()
+                        .map(|cell| u32::try_from(cell.to_i128()).ok().and_then(char::from_u32).unwrap_or('?'))
+                        .collect()
+                };
+                self.write(&text)?;
+                self.stack.truncate(length - 2);
+            },
+            Builtin::Base => self.stack.push(base_address()),
+            Builtin::Immediate => {
+                if self.dictionary.len() == BASE_TOKENS.len() {
+                    return Err(Error::InvalidWord);
+                }
+                Rc::make_mut(self.dictionary.last_mut().unwrap()).immediate = true;
+            },
+            Builtin::Hex => self.base = 16,
+            Builtin::Decimal => self.base = 10,
+            Builtin::Words => {
+                let text: String = self.words().iter().map(|name| format!("{name} ")).collect();
+                self.write(&text)?;
+            },
+            Builtin::DotS => {
+                let mut text = format!("<{length}> ");
+                for value in &self.stack {
+                    text.push_str(&format!("{} ", value.format(self.base)));
+                }
+                self.write(&text)?;
+            },
+            Builtin::Emit => {
+                self.require(1)?;
+                let ch = u32::try_from(self.stack[length - 1].to_i128()).ok()
+                    .and_then(char::from_u32)
+                    .unwrap_or(char::REPLACEMENT_CHARACTER);
+                self.write(ch.encode_utf8(&mut [0; 4]))?;
+                self.stack.pop();
+            },
+            Builtin::Cr => self.write("\n")?,
+            Builtin::Space => self.write(" ")?,
+            Builtin::Spaces => {
+                self.require(1)?;
+                let mut count = usize::try_from(self.stack[length - 1].to_i128()).unwrap_or(0);
+                // Written in pieces so a huge count never turns into one huge allocation.
+                while count > 0 {
+                    let chunk = count.min(BLANKS.len());
+                    self.write(&BLANKS[..chunk])?;
+                    count -= chunk;
+                }
+                self.stack.pop();
+            },
+            Builtin::Throw => {
+                self.require(1)?;
+                let code = self.stack.pop().unwrap();
+                if code != C::default() {
+                    return Err(Error::Thrown(i32::try_from(code.to_i128()).unwrap_or(i32::MIN)));
+                }
+            },
+            #[cfg(feature = "float")]
+            Builtin::FAdd | Builtin::FSub | Builtin::FMul | Builtin::FDiv | Builtin::FDup | Builtin::FDrop
+                | Builtin::FSwap | Builtin::FDot | Builtin::FSqrt | Builtin::FLt => self.float_operation(op)?,
+            // Both need the call frames and are handled by `dispatch`.
+            Builtin::Execute | Builtin::Catch => return Err(Error::ControlStructureMismatch),
         };
-        if length < min_length_need {
-            return  Err(Error::StackUnderflow);
+
+        Ok(())
+    }
+
+    fn literal(&self, word: &str) -> Option<Instruction<C>> {
+        if let Some(number) = self.parse_number(word) {
+            return Some(Instruction::Push(number));
+        }
+        #[cfg(feature = "float")]
+        if let Some(value) = self.parse_float(word) {
+            return Some(Instruction::FPush(value.to_bits()));
+        }
+        None
+    }
+
+    // Only decimal tokens with an exponent are floats, as in `1.5E0` or `2E`.
+    #[cfg(feature = "float")]
+    fn parse_float(&self, word: &str) -> Option<f64> {
+        let valid = self.base == 10 && word.contains('E')
+            && word.bytes().all(|b| b.is_ascii_digit() || b"+-.E".contains(&b));
+        if !valid {
+            return None;
         }
+        if word.ends_with('E') { format!("{word}0").parse().ok() } else { word.parse().ok() }
+    }
 
-        let (last_element, last_idx) = if min_length_need == 2 && operation_name != "OVER" {
-            (self.stack.pop(), length - 2)
-        } else {
-            (None, length - 1)
-        };
-        match operation_name {
-            "+" => self.stack[last_idx] += last_element.unwrap(),
-            "-" => self.stack[last_idx] -= last_element.unwrap(),
-            "*" => self.stack[last_idx] *= last_element.unwrap(),
-            "/" => {
-                if last_element.unwrap() == 0 {
-                    return Err(Error::DivisionByZero);
+    fn parse_number(&self, word: &str) -> Option<C> {
+        if is_char_literal(word) {
+            return word.chars().nth(1).map(|ch| cell(ch as usize));
+        }
+        let (base, digits) = self.radix(word);
+        C::parse(digits, base)
+    }
+
+    fn parse_double(&self, word: &str) -> Option<(C, C)> {
+        let (base, digits) = self.radix(word.strip_suffix('.')?);
+        i128::from_str_radix(digits, base).ok().filter(|value| fits_double::<C>(*value)).map(split)
+    }
+
+    fn radix<'a>(&self, word: &'a str) -> (u32, &'a str) {
+        match word.as_bytes().first() {
+            Some(b'$') => (16, &word[1..]),
+            Some(b'#') => (10, &word[1..]),
+            Some(b'%') => (2, &word[1..]),
+            _ => (self.base, word),
+        }
+    }
+
+    fn store_string(&mut self, text: &str) -> std::result::Result<(C, C), Error> {
+        let address = self.memory.len();
+        let length = text.chars().count();
+        self.allot(C::from_i128(length as i128).ok_or(Error::InvalidAddress)?)?;
+        for (cell, ch) in self.memory[address..].iter_mut().zip(text.chars()) {
+            *cell = C::wrapping_from_i128(ch as i128);
+        }
+        Ok((cell(address), cell(length)))
+    }
+
+    fn fetch(&self, address: C) -> std::result::Result<C, Error> {
+        if address == base_address() {
+            return Ok(cell(self.base as usize));
+        }
+        Ok(self.memory[self.address(address)?])
+    }
+
+    fn store(&mut self, address: C, value: C) -> Result {
+        if address == base_address() {
+            let base = u32::try_from(value.to_i128()).ok()
+                .filter(|base| (2..=36).contains(base))
+                .ok_or(Error::InvalidAddress)?;
+            self.base = base;
+            return Ok(());
+        }
+        let address = self.address(address)?;
+        self.memory[address] = value;
+        Ok(())
+    }
+
+    fn address(&self, address: C) -> std::result::Result<usize, Error> {
+        usize::try_from(address.to_i128()).ok()
+            .filter(|address| *address < self.memory.len())
+            .ok_or(Error::InvalidAddress)
+    }
+
+    fn allot(&mut self, cells: C) -> Result {
+        let here = self.memory.len() as i128 + cells.to_i128();
+        if here < 0 {
+            return Err(Error::InvalidAddress);
+        }
+        // HERE must stay a valid address, and with unsigned cells MAX itself is the BASE slot.
+        if here > self.limits.max_memory_cells as i128 || here > C::MAX.to_i128() {
+            return Err(Error::LimitExceeded(Limit::MemoryCells));
+        }
+        self.memory.resize(here as usize, C::default());
+        Ok(())
+    }
+
+    #[cfg(feature = "float")]
+    fn float_operation(&mut self, op: Builtin) -> Result {
+        let depth = match op {
+            Builtin::FDup | Builtin::FDrop | Builtin::FDot | Builtin::FSqrt => 1,
+            _ => 2,
+        };
+        if self.float_stack.len() < depth {
+            return Err(Error::StackUnderflow);
+        }
+        let b = self.float_stack[self.float_stack.len() - 1];
+        match op {
+            Builtin::FDiv if b == 0.0 => return Err(Error::DivisionByZero),
+            Builtin::FDot => self.write(&format!("{b} "))?,
+            _ => {},
+        }
+        self.float_stack.pop();
+        match op {
+            Builtin::FDup => self.float_stack.extend([b, b]),
+            Builtin::FSqrt => self.float_stack.push(b.sqrt()),
+            Builtin::FDrop | Builtin::FDot => {},
+            _ => {
+                let a = self.float_stack.pop().unwrap();
+                match op {
+                    Builtin::FAdd => self.float_stack.push(a + b),
+                    Builtin::FSub => self.float_stack.push(a - b),
+                    Builtin::FMul => self.float_stack.push(a * b),
+                    Builtin::FDiv => self.float_stack.push(a / b),
+                    Builtin::FSwap => self.float_stack.extend([b, a]),
+                    _ => self.stack.push(flag(a < b)),
                 }
-                self.stack[last_idx] /= last_element.unwrap()
             },
-            "DUP" => self.stack.push(self.stack[last_idx]),
-            "OVER" => self.stack.push(self.stack[length-2]),
-            "DROP" => _ = self.stack.pop(),
-            "SWAP" => {
-                let tmp = self.stack[last_idx];
-                self.stack[last_idx] = last_element.unwrap();
-                self.stack.push(tmp);
+        }
+        Ok(())
+    }
+
+    fn require(&self, depth: usize) -> Result {
+        if self.stack.len() < depth {
+            return Err(Error::StackUnderflow);
+        }
+        Ok(())
+    }
+
+    fn unary_operation<F>(&mut self, operation: F) -> Result
+    where F: Fn(C) -> std::result::Result<C, Error> {
+        self.require(1)?;
+        let last_idx = self.stack.len() - 1;
+        self.stack[last_idx] = operation(self.stack[last_idx])?;
+        Ok(())
+    }
+
+    fn binary_operation<F>(&mut self, operation: F) -> Result
+    where F: Fn(C, C) -> std::result::Result<C, Error> {
+        self.require(2)?;
+        let last_idx = self.stack.len() - 2;
+        self.stack[last_idx] = operation(self.stack[last_idx], self.stack[last_idx + 1])?;
+        self.stack.pop();
+        Ok(())
+    }
+
+    fn start_user_operation(&mut self, words: &mut Tokens) -> Result {
+        let key = definition_name::<C>(words.next())?;
+        self.compiling = Some(Definition {
+            key,
+            instructions: Vec::new(),
+            control: Vec::new(),
+            here: self.memory.len(),
+            suspended: false,
+        });
+        Ok(())
+    }
+
+    fn compile(&mut self, word: &str, words: &mut Tokens) -> Result {
+        // Immediate words run now, with the definition left in place for them to extend.
+        if let Some(&idx) = self.dictionary_idx.get(word).filter(|idx| self.dictionary[**idx].immediate) {
+            let result = self.run(self.compile_entry(idx));
+            if result.is_err() {
+                self.compiling = None;
+            }
+            return result;
+        }
+        let Some(mut definition) = self.compiling.take() else {
+            return Ok(());
+        };
+        if word == "[" {
+            definition.suspended = true;
+            self.compiling = Some(definition);
+            return Ok(());
+        }
+        if word == ";" {
+            if !definition.control.is_empty() {
+                return Err(Error::ControlStructureMismatch);
+            }
+            return self.insert_user_operation(definition.key, definition.instructions, definition.here);
+        }
+        self.compile_into(&mut definition, word, words)?;
+        if definition.instructions.len() > self.limits.max_definition_length {
+            return Err(Error::LimitExceeded(Limit::DefinitionLength));
+        }
+        self.compiling = Some(definition);
+        Ok(())
+    }
+
+    fn compile_into(&mut self, definition: &mut Definition<C>, word: &str, words: &mut Tokens) -> Result {
+        if self.dictionary_idx.contains_key(word) {
+            definition.instructions.push(self.compile_word(word)?);
+        } else if word == "'" || word == "[']" {
+            let xt = self.find_word(words.next())?;
+            definition.instructions.push(Instruction::PushXt(xt));
+        } else if word == ".\"" {
+            definition.instructions.push(Instruction::Print(self.strings.len()));
+            self.strings.push(words.parse_until('"').to_string());
+        } else if word == "S\"" {
+            let text = words.parse_until('"');
+            let address = self.memory.len();
+            self.store_string(text)?;
+            definition.instructions.push(Instruction::PushString(address, self.strings.len()));
+            self.strings.push(text.to_string());
+        } else if word == "CHAR" || word == "[CHAR]" {
+            let ch = words.next().and_then(|word| word.chars().next()).ok_or(Error::InvalidWord)?;
+            definition.instructions.push(Instruction::Push(cell(ch as usize)));
+        } else if word == "LITERAL" {
+            let value = self.stack.pop().ok_or(Error::StackUnderflow)?;
+            definition.instructions.push(Instruction::Push(value));
+        } else if word == "POSTPONE" {
+            let name = words.next().ok_or(Error::InvalidWord)?.to_ascii_uppercase();
+            let instruction = match self.dictionary_idx.get(&name) {
+                Some(&idx) if self.dictionary[idx].immediate => self.compile_entry(idx),
+                Some(&idx) => Instruction::CompileWord(idx),
+                None if CONTROL_WORDS.contains(&name.as_str()) => {
+                    self.strings.push(name);
+                    Instruction::CompileToken(self.strings.len() - 1)
+                },
+                None => return Err(Error::UnknownWord),
+            };
+            definition.instructions.push(instruction);
+        } else if let Some((low, high)) = self.parse_double(word) {
+            definition.instructions.extend([Instruction::Push(low), Instruction::Push(high)]);
+        } else if word == ":" {
+            return Err(Error::ControlStructureMismatch);
+        } else if !definition.compile_control(word)? {
+            definition.instructions.push(self.compile_word(word)?);
+        }
+        Ok(())
+    }
+
+    fn add_data_operation(&mut self, defining_word: &str, key: String) -> Result {
+        self.check_dictionary_size()?;
+        let here = self.memory.len();
+        let word = match defining_word {
+            "VARIABLE" => {
+                self.allot(cell(1))?;
+                Word::Colon(vec![Instruction::Push(cell(here))])
+            },
+            "2VARIABLE" => {
+                self.allot(cell(2))?;
+                Word::Colon(vec![Instruction::Push(cell(here))])
+            },
+            "CREATE" => Word::Colon(vec![Instruction::Push(cell(here))]),
+            "2CONSTANT" => {
+                self.require(2)?;
+                let high = self.stack.pop().unwrap();
+                let low = self.stack.pop().unwrap();
+                Word::Colon(vec![Instruction::Push(low), Instruction::Push(high)])
             },
-            _ => return Err(Error::UnknownWord)
+            "MARKER" => Word::Builtin(Instruction::Forget(self.seq_id)),
+            _ => Word::Colon(vec![Instruction::Push(self.stack.pop().ok_or(Error::StackUnderflow)?)]),
         };
+        self.insert_word(key, word, here);
+        if defining_word != "MARKER" {
+            let defined_by = DEFINING_WORDS.iter().copied().find(|word| *word == defining_word);
+            Rc::make_mut(self.dictionary.last_mut().unwrap()).defined_by = defined_by;
+        }
+        Ok(())
+    }
 
+    pub fn define_native<F>(&mut self, name: &str, arity: usize, function: F) -> Result
+    where F: Fn(&mut Stack<C>) -> Result + 'static {
+        let key = definition_name::<C>(Some(name))?;
+        self.check_dictionary_size()?;
+        let native = Native { arity, function: Rc::new(function) };
+        self.insert_word(key, Word::Native(Instruction::Native(self.seq_id), native), self.memory.len());
         Ok(())
     }
-    
-    fn add_user_operation(&mut self, input: &str) -> Result {
-        let mut key = String::new();
-        let mut words = String::new();
-        let mut tmp_word = String::new();
 
-        for ch in input[2..].chars() {
-            if ch.is_ascii_whitespace() {
-                if key.is_empty() {
-                    if tmp_word.parse::<Value>().is_err() {
-                        key.push_str(&tmp_word);
-                    } else {
-                        return Err(Error::InvalidWord);
-                    }
-                } else {
-                    if let Some(idx) = self.user_operations_idx.get(&tmp_word) {
-                        words.push_str(&format!("{IDX_PREFIX}{idx}"));
-                    } else if is_base_token(&tmp_word) || tmp_word.parse::<Value>().is_ok() {
-                        words.push_str(&tmp_word);
-                    } else {
-                        return Err(Error::UnknownWord);
-                    }
-                    words.push(' ');
+    fn call_native(&mut self, idx: usize) -> Result {
+        let Word::Native(_, native) = &self.dictionary[idx].word else {
+            return Err(Error::InvalidWord);
+        };
+        if self.stack.len() < native.arity {
+            return Err(Error::StackUnderflow);
+        }
+        // Put the arguments back if the host function fails, like the built-ins do.
+        let base = self.stack.len() - native.arity;
+        let arguments = self.stack[base..].to_vec();
+        let result = (native.function)(&mut self.stack);
+        if result.is_err() {
+            self.stack.truncate(base);
+            self.stack.extend(arguments);
+        }
+        result
+    }
+
+    fn insert_user_operation(&mut self, key: String, instructions: Vec<Instruction<C>>, here: usize) -> Result {
+        self.check_dictionary_size()?;
+        self.insert_word(key, Word::Colon(instructions), here);
+        Ok(())
+    }
+
+    fn check_dictionary_size(&self) -> Result {
+        let full = self.dictionary.len() - BASE_TOKENS.len() >= self.limits.max_dictionary_size;
+        // Every word's execution token has to fit in a cell.
+        if full || self.dictionary.len() as i128 > C::MAX.to_i128() {
+            return Err(Error::LimitExceeded(Limit::DictionarySize));
+        }
+        Ok(())
+    }
+
+    fn insert_word(&mut self, name: String, word: Word<C>, here: usize) {
+        self.dictionary.push(Rc::new(Entry { name: name.clone(), word, here, immediate: false, defined_by: None }));
+        self.dictionary_idx.insert(name, self.seq_id);
+        self.seq_id += 1;
+    }
+
+    fn run(&mut self, instruction: Instruction<C>) -> Result {
+        if self.nesting >= self.limits.max_call_depth.min(MAX_NESTED_RUNS) {
+            return Err(Error::LimitExceeded(Limit::CallDepth));
+        }
+        self.nesting += 1;
+        let loop_depth = self.loop_stack.len();
+        let return_depth = self.return_stack.len();
+        let mut frames = Vec::new();
+        let mut result = self.dispatch(instruction, &mut frames);
+        loop {
+            if let Err(error) = result {
+                result = self.throw(&mut frames, error);
+                if result.is_err() {
+                    break;
                 }
-                tmp_word.clear();
-            } else if ch == ';' {
-                words.remove(words.len() -1);
+            }
+            if frames.is_empty() {
                 break;
-            } else {
-                tmp_word.push(ch.to_ascii_uppercase());
             }
+            result = self.step_frame(&mut frames);
         }
+        self.loop_stack.truncate(loop_depth);
+        self.return_stack.truncate(return_depth);
+        self.nesting -= 1;
+        result
+    }
 
-        self.insert_user_operation(key, words)
+    fn step_frame(&mut self, frames: &mut Vec<Frame<C>>) -> Result {
+        let frame = current_frame(frames)?;
+        let Some(&instruction) = self.dictionary[frame.word].word.instructions().get(frame.pc) else {
+            return self.leave_frame(frames);
+        };
+        frame.pc += 1;
+        self.count_instruction()?;
+        self.dispatch(instruction, frames)?;
+        self.check_stack_depth()
     }
 
-    fn insert_user_operation(&mut self, key: String, words: String) -> Result {
-        self.user_operations.push(words);
-        self.user_operations_idx.insert(key, self.seq_id);
-        self.seq_id += 1;
+    fn enter_frame(&mut self, frames: &mut Vec<Frame<C>>, word: usize, catch: Option<usize>) -> Result {
+        if frames.len() >= self.limits.max_call_depth {
+            return Err(Error::LimitExceeded(Limit::CallDepth));
+        }
+        frames.push(Frame {
+            word,
+            pc: 0,
+            loop_depth: self.loop_stack.len(),
+            return_depth: self.return_stack.len(),
+            catch,
+            before: None,
+        });
+        Ok(())
+    }
+
+    fn leave_frame(&mut self, frames: &mut Vec<Frame<C>>) -> Result {
+        let frame = current_frame(frames)?;
+        if self.return_stack.len() != frame.return_depth {
+            return Err(Error::ReturnStackImbalance);
+        }
+        self.loop_stack.truncate(frame.loop_depth);
+        let frame = frames.pop().unwrap();
+        if frame.catch.is_some() {
+            self.stack.push(C::default());
+        }
+        if let Some(before) = frame.before {
+            self.report(self.dictionary[frame.word].name.clone(), frames.len(), before);
+        }
+        Ok(())
+    }
+
+    fn throw(&mut self, frames: &mut Vec<Frame<C>>, error: Error) -> Result {
+        let handler = frames.iter().rposition(|frame| frame.catch.is_some());
+        let (Some(code), Some(handler)) = (error.throw_code(), handler) else {
+            self.trace = frames.iter().map(|frame| self.dictionary[frame.word].name.clone()).collect();
+            return Err(error);
+        };
+        frames.truncate(handler + 1);
+        let frame = frames.pop().unwrap();
+        self.stack.resize(frame.catch.unwrap(), C::default());
+        self.stack.push(C::wrapping_from_i128(code.into()));
+        self.loop_stack.truncate(frame.loop_depth);
+        self.return_stack.truncate(frame.return_depth);
         Ok(())
     }
-    
-    fn eval_user_operation(&mut self, operation_name: &str) -> Result {
-        if let Some(command) =  self.user_operations_idx.get(operation_name) {
-            self.eval(&self.user_operations[*command].clone())
+
+    fn pop_execution_token(&mut self) -> std::result::Result<usize, Error> {
+        self.require(1)?;
+        let xt = usize::try_from(self.stack[self.stack.len() - 1].to_i128()).ok()
+            .filter(|xt| *xt < self.dictionary.len())
+            .ok_or(Error::InvalidAddress)?;
+        self.stack.pop();
+        Ok(xt)
+    }
+
+    fn dispatch(&mut self, instruction: Instruction<C>, frames: &mut Vec<Frame<C>>) -> Result {
+        if self.trace_hook.is_none() {
+            return self.execute(instruction, frames);
+        }
+        let depth = frames.len();
+        let before = self.stack.clone();
+        self.execute(instruction, frames)?;
+        // A word that entered a frame is reported when it returns, once its stack effect is known.
+        if frames.len() > depth {
+            frames.last_mut().unwrap().before = Some(before);
         } else {
-            Ok(())
+            self.report(self.instruction_name(instruction), depth, before);
         }
+        Ok(())
     }
 
-    fn eval_internal_operation(&mut self, operation_name: &str) -> Result {
-        let idx = operation_name[1..].parse::<usize>().unwrap();
-        self.eval(&self.user_operations[idx].clone())
+    fn report(&mut self, word: String, depth: usize, before: Vec<C>) {
+        if let Some(hook) = self.trace_hook.as_mut() {
+            hook(&TraceEvent { word, depth, before, after: self.stack.clone() });
+        }
     }
 
+    fn execute(&mut self, instruction: Instruction<C>, frames: &mut Vec<Frame<C>>) -> Result {
+        match instruction {
+            Instruction::Call(idx) => self.enter_frame(frames, idx, None)?,
+            Instruction::Builtin(Builtin::Execute) => {
+                let xt = self.pop_execution_token()?;
+                self.enter_frame(frames, xt, None)?;
+            },
+            Instruction::Builtin(Builtin::Catch) => {
+                let xt = self.pop_execution_token()?;
+                self.enter_frame(frames, xt, Some(self.stack.len()))?;
+            },
+            Instruction::Recurse => {
+                let word = current_frame(frames)?.word;
+                self.enter_frame(frames, word, None)?;
+            },
+            Instruction::Exit => self.leave_frame(frames)?,
+            // Rolling back the dictionary would pull running definitions out from under their frames.
+            Instruction::Forget(_) if !frames.is_empty() => return Err(Error::InvalidWord),
+            Instruction::Forget(idx) => self.forget(idx)?,
+            Instruction::Branch(target) => current_frame(frames)?.pc = target,
+            Instruction::BranchIfZero(target) => {
+                let frame = current_frame(frames)?;
+                if self.stack.pop().ok_or(Error::StackUnderflow)? == C::default() {
+                    frame.pc = target;
+                }
+            },
+            Instruction::ToR => {
+                current_frame(frames)?;
+                let value = self.stack.pop().ok_or(Error::StackUnderflow)?;
+                self.return_stack.push(value);
+            },
+            Instruction::FromR | Instruction::RFetch => {
+                if self.return_stack.len() <= current_frame(frames)?.return_depth {
+                    return Err(Error::ReturnStackUnderflow);
+                }
+                let value = if instruction == Instruction::FromR {
+                    self.return_stack.pop().unwrap()
+                } else {
+                    self.return_stack[self.return_stack.len() - 1]
+                };
+                self.stack.push(value);
+            },
+            Instruction::Loop(body) => {
+                let frame = current_frame(frames)?;
+                let (index, limit) = self.loop_stack.last_mut()
+                    .ok_or(Error::ControlStructureMismatch)?;
+                *index = index.wrapping_add(cell(1));
+                if index == limit {
+                    self.loop_stack.pop();
+                } else {
+                    frame.pc = body;
+                }
+            },
+            Instruction::PlusLoop(body) => {
+                let frame = current_frame(frames)?;
+                let step = self.stack.pop().ok_or(Error::StackUnderflow)?;
+                let (index, limit) = self.loop_stack.last_mut()
+                    .ok_or(Error::ControlStructureMismatch)?;
+                let before = index.wrapping_sub(*limit);
+                let after = before.wrapping_add(step);
+                *index = index.wrapping_add(step);
+                // Only a step that moves across the limit ends the loop, not one that wraps around MAX.
+                if ((before ^ after) & (before ^ step)).is_negative() {
+                    self.loop_stack.pop();
+                } else {
+                    frame.pc = body;
+                }
+            },
+            instruction => self.exec_instruction(instruction)?,
+        }
+        Ok(())
+    }
 }
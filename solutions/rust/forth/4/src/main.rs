@@ -0,0 +1,70 @@
+use forth::{DirectoryResolver, Forth};
+use std::io::{self, BufRead, Write};
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    let mut forth = Forth::new();
+    forth.set_output(io::stdout());
+    forth.set_resolver(DirectoryResolver::new("."));
+
+    for path in std::env::args().skip(1) {
+        let source = match std::fs::read_to_string(&path) {
+            Ok(source) => source,
+            Err(error) => {
+                eprintln!("{path}: {error}");
+                return ExitCode::FAILURE;
+            },
+        };
+        if let Err(error) = forth.eval(&source) {
+            eprintln!("{path}: {error}");
+            return ExitCode::FAILURE;
+        }
+    }
+
+    let mut history: Vec<String> = Vec::new();
+    let mut lines = io::stdin().lock().lines();
+    loop {
+        print!("{}", if forth.is_compiling() { "] " } else { "> " });
+        let _ = io::stdout().flush();
+        let Some(Ok(mut line)) = lines.next() else {
+            println!();
+            return ExitCode::SUCCESS;
+        };
+
+        match line.trim() {
+            "!history" => {
+                for (idx, entry) in history.iter().enumerate() {
+                    println!("{:>4}  {entry}", idx + 1);
+                }
+                continue;
+            },
+            // A bare `!` is the Forth store word, so only `!` followed by digits recalls history.
+            command if command.len() > 1 && command.starts_with('!')
+                && command[1..].bytes().all(|b| b.is_ascii_digit()) => {
+                match command[1..].parse::<usize>().ok().and_then(|idx| history.get(idx.wrapping_sub(1))) {
+                    Some(entry) => {
+                        line = entry.clone();
+                        println!("{line}");
+                    },
+                    None => {
+                        println!("no such history entry: {command}");
+                        continue;
+                    },
+                }
+            },
+            _ => {},
+        }
+        if !line.trim().is_empty() {
+            history.push(line.clone());
+        }
+
+        // A failed line is rolled back so a typo does not leave half a definition behind.
+        let result = forth.eval_atomic(&line);
+        let _ = io::stdout().flush();
+        match result {
+            Ok(()) if forth.is_compiling() => println!(" compiled"),
+            Ok(()) => println!(" ok"),
+            Err(error) => println!("\n{error}"),
+        }
+    }
+}
@@ -483,3 +483,2201 @@ mod case_insensitivity {
         assert_eq!(f.stack(), [1, 1, 1, 1]);
     }
 }
+
+mod compiled_definitions {
+    use forth::*;
+
+    #[test]
+    #[ignore]
+    fn definitions_resolve_words_when_compiled() {
+        let mut f = Forth::new();
+        assert_eq!(f.eval(": foo bar ;"), Err(Error::UnknownWord));
+        assert!(f.eval(": bar 1 ;").is_ok());
+        assert!(f.eval(": foo bar ;").is_ok());
+        assert!(f.eval(": bar 2 ;").is_ok());
+        assert!(f.eval("foo bar").is_ok());
+        assert_eq!(f.stack(), [1, 2]);
+    }
+
+    #[test]
+    #[ignore]
+    fn deeply_nested_definitions_do_not_recurse_natively() {
+        let mut f = Forth::new();
+        assert!(f.eval(": w0 1 + ;").is_ok());
+        for i in 1..100_000 {
+            assert!(f.eval(&format!(": w{i} w{} ;", i - 1)).is_ok());
+        }
+        assert!(f.eval("0 w99999").is_ok());
+        assert_eq!(f.stack(), [1]);
+    }
+}
+
+mod conditionals {
+    use forth::*;
+
+    #[test]
+    #[ignore]
+    fn if_then_runs_body_when_true() {
+        let mut f = Forth::new();
+        assert!(f.eval(": f if 10 then 20 ;").is_ok());
+        assert!(f.eval("1 f 0 f").is_ok());
+        assert_eq!(f.stack(), [10, 20, 20]);
+    }
+
+    #[test]
+    #[ignore]
+    fn any_non_zero_value_is_true() {
+        let mut f = Forth::new();
+        assert!(f.eval(": f if 1 else 0 then ;").is_ok());
+        assert!(f.eval("-7 f 3 f 0 f").is_ok());
+        assert_eq!(f.stack(), [1, 1, 0]);
+    }
+
+    #[test]
+    #[ignore]
+    fn nested_conditionals() {
+        let mut f = Forth::new();
+        assert!(f.eval(": sign dup if 0 swap - if 1 else 2 then else drop 3 then ;").is_ok());
+        assert!(f.eval("5 sign 0 sign").is_ok());
+        assert_eq!(f.stack(), [1, 3]);
+    }
+
+    #[test]
+    #[ignore]
+    fn errors_if_condition_is_missing() {
+        let mut f = Forth::new();
+        assert!(f.eval(": f if 1 then ;").is_ok());
+        assert_eq!(f.eval("f"), Err(Error::StackUnderflow));
+    }
+
+    #[test]
+    #[ignore]
+    fn errors_on_unbalanced_control_words() {
+        let mut f = Forth::new();
+        assert_eq!(f.eval(": f if 1 ;"), Err(Error::ControlStructureMismatch));
+        assert_eq!(f.eval(": f 1 then ;"), Err(Error::ControlStructureMismatch));
+        assert_eq!(f.eval(": f else 1 then ;"), Err(Error::ControlStructureMismatch));
+        assert_eq!(f.eval(": f if 1 else 2 else 3 then ;"), Err(Error::ControlStructureMismatch));
+    }
+
+    #[test]
+    #[ignore]
+    fn errors_on_control_words_outside_definitions() {
+        let mut f = Forth::new();
+        assert_eq!(f.eval("1 if 2 then"), Err(Error::ControlStructureMismatch));
+    }
+}
+
+mod loops {
+    use forth::*;
+
+    #[test]
+    #[ignore]
+    fn do_loop_counts_from_start_to_limit() {
+        let mut f = Forth::new();
+        assert!(f.eval(": f 5 0 do i loop ;").is_ok());
+        assert!(f.eval("f").is_ok());
+        assert_eq!(f.stack(), [0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    #[ignore]
+    fn plus_loop_uses_the_given_step() {
+        let mut f = Forth::new();
+        assert!(f.eval(": up 10 0 do i 3 +loop ;").is_ok());
+        assert!(f.eval(": down 0 4 do i -2 +loop ;").is_ok());
+        assert!(f.eval("up down").is_ok());
+        assert_eq!(f.stack(), [0, 3, 6, 9, 4, 2, 0]);
+    }
+
+    #[test]
+    #[ignore]
+    fn nested_loops_expose_outer_index_with_j() {
+        let mut f = Forth::new();
+        assert!(f.eval(": f 3 1 do 3 1 do j i * loop loop ;").is_ok());
+        assert!(f.eval("f").is_ok());
+        assert_eq!(f.stack(), [1, 2, 2, 4]);
+    }
+
+    #[test]
+    #[ignore]
+    fn begin_until_repeats_until_true() {
+        let mut f = Forth::new();
+        assert!(f.eval(": countup begin dup 1 + dup 3 - if 0 else 1 then until ;").is_ok());
+        assert!(f.eval("0 countup").is_ok());
+        assert_eq!(f.stack(), [0, 1, 2, 3]);
+    }
+
+    #[test]
+    #[ignore]
+    fn begin_while_repeat_checks_before_each_iteration() {
+        let mut f = Forth::new();
+        assert!(f.eval(": sum 0 swap begin dup while swap over + swap 1 - repeat drop ;").is_ok());
+        assert!(f.eval("4 sum 0 sum").is_ok());
+        assert_eq!(f.stack(), [10, 0]);
+    }
+
+    #[test]
+    #[ignore]
+    fn errors_if_loop_bounds_are_missing() {
+        let mut f = Forth::new();
+        assert!(f.eval(": f do i loop ;").is_ok());
+        assert_eq!(f.eval("1 f"), Err(Error::StackUnderflow));
+    }
+
+    #[test]
+    #[ignore]
+    fn errors_on_malformed_loops() {
+        let mut f = Forth::new();
+        assert_eq!(f.eval(": f 5 0 do i ;"), Err(Error::ControlStructureMismatch));
+        assert_eq!(f.eval(": f i ;"), Err(Error::ControlStructureMismatch));
+        assert_eq!(f.eval(": f 5 0 do j loop ;"), Err(Error::ControlStructureMismatch));
+        assert_eq!(f.eval(": f begin 1 repeat ;"), Err(Error::ControlStructureMismatch));
+        assert_eq!(f.eval(": f begin 1 while until ;"), Err(Error::ControlStructureMismatch));
+        assert_eq!(f.eval(": f 1 if begin then until ;"), Err(Error::ControlStructureMismatch));
+        assert_eq!(f.eval("5 0 do i loop"), Err(Error::ControlStructureMismatch));
+    }
+
+    #[test]
+    #[ignore]
+    fn plus_loop_runs_past_the_signed_wraparound_like_loop() {
+        // With 16-bit cells both loops step from 10 through 32767 and 32768 until they wrap to 0.
+        let mut f = Forth::<std::num::Wrapping<u16>>::default();
+        assert!(f.eval(": a 0 0 10 do 1 + loop ; : b 0 0 10 do 1 + 1 +loop ; a b").is_ok());
+        assert_eq!(f.stack(), [std::num::Wrapping(65526); 2]);
+    }
+}
+
+mod comparison_and_logic {
+    use forth::*;
+
+    #[test]
+    #[ignore]
+    fn comparisons_push_standard_flags() {
+        let mut f = Forth::new();
+        assert!(f.eval("1 1 = 1 2 = 1 2 < 2 1 < 2 1 > 1 2 <> 0 0= 5 0= -3 0< 3 0<").is_ok());
+        assert_eq!(f.stack(), [-1, 0, -1, 0, -1, -1, -1, 0, -1, 0]);
+    }
+
+    #[test]
+    #[ignore]
+    fn bitwise_words() {
+        let mut f = Forth::new();
+        assert!(f.eval("12 10 and 12 10 or 12 10 xor 0 invert").is_ok());
+        assert_eq!(f.stack(), [8, 14, 6, -1]);
+    }
+
+    #[test]
+    #[ignore]
+    fn errors_if_there_is_only_one_value_on_the_stack() {
+        let mut f = Forth::new();
+        assert_eq!(f.eval("1 ="), Err(Error::StackUnderflow));
+        assert_eq!(f.eval("and"), Err(Error::StackUnderflow));
+    }
+}
+
+mod extended_arithmetic {
+    use forth::*;
+
+    #[test]
+    #[ignore]
+    fn sign_and_bounds() {
+        let mut f = Forth::new();
+        assert!(f.eval("5 negate -5 abs 3 7 min 3 7 max").is_ok());
+        assert_eq!(f.stack(), [-5, 5, 3, 7]);
+    }
+
+    #[test]
+    #[ignore]
+    fn remainder_and_quotient() {
+        let mut f = Forth::new();
+        assert!(f.eval("13 5 mod 13 5 /mod").is_ok());
+        assert_eq!(f.stack(), [3, 3, 2]);
+    }
+
+    #[test]
+    #[ignore]
+    fn scaling_uses_a_wide_intermediate_product() {
+        let mut f = Forth::new();
+        assert!(f.eval("100000 100000 1000000 */").is_ok());
+        assert_eq!(f.stack(), [10000]);
+    }
+
+    #[test]
+    #[ignore]
+    fn errors_if_dividing_by_zero() {
+        let mut f = Forth::new();
+        assert_eq!(f.eval("1 0 mod"), Err(Error::DivisionByZero));
+        assert_eq!(f.eval("1 0 /mod"), Err(Error::DivisionByZero));
+        assert_eq!(f.eval("1 2 0 */"), Err(Error::DivisionByZero));
+    }
+
+    #[test]
+    #[ignore]
+    fn errors_if_there_are_not_enough_values_on_the_stack() {
+        let mut f = Forth::new();
+        assert_eq!(f.eval("negate"), Err(Error::StackUnderflow));
+        assert_eq!(f.eval("1 /mod"), Err(Error::StackUnderflow));
+        assert_eq!(f.eval("*/"), Err(Error::StackUnderflow));
+    }
+}
+
+mod stack_manipulation {
+    use forth::*;
+
+    #[test]
+    #[ignore]
+    fn rotations() {
+        let mut f = Forth::new();
+        assert!(f.eval("1 2 3 rot 4 5 6 -rot").is_ok());
+        assert_eq!(f.stack(), [2, 3, 1, 6, 4, 5]);
+    }
+
+    #[test]
+    #[ignore]
+    fn nip_and_tuck() {
+        let mut f = Forth::new();
+        assert!(f.eval("1 2 nip 3 tuck").is_ok());
+        assert_eq!(f.stack(), [3, 2, 3]);
+    }
+
+    #[test]
+    #[ignore]
+    fn pair_words() {
+        let mut f = Forth::new();
+        assert!(f.eval("1 2 2dup 3 4 2swap 2over 9 9 2drop").is_ok());
+        assert_eq!(f.stack(), [1, 2, 3, 4, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    #[ignore]
+    fn question_dup_only_copies_non_zero() {
+        let mut f = Forth::new();
+        assert!(f.eval("0 ?dup 1 ?dup").is_ok());
+        assert_eq!(f.stack(), [0, 1, 1]);
+    }
+
+    #[test]
+    #[ignore]
+    fn depth_pick_and_roll() {
+        let mut f = Forth::new();
+        assert!(f.eval("depth 10 20 30 2 pick 3 roll depth").is_ok());
+        assert_eq!(f.stack(), [0, 20, 30, 10, 10, 5]);
+    }
+
+    #[test]
+    #[ignore]
+    fn errors_if_picking_beyond_the_stack() {
+        let mut f = Forth::new();
+        assert_eq!(f.eval("1 2 2 pick"), Err(Error::StackUnderflow));
+        let mut f = Forth::new();
+        assert_eq!(f.eval("1 -1 roll"), Err(Error::StackUnderflow));
+        let mut f = Forth::new();
+        assert_eq!(f.eval("1 2 3 2swap"), Err(Error::StackUnderflow));
+    }
+}
+
+mod overflow {
+    use forth::*;
+
+    #[test]
+    #[ignore]
+    fn errors_on_overflowing_arithmetic() {
+        let mut f = Forth::new();
+        assert_eq!(f.eval("2147483647 1 +"), Err(Error::Overflow));
+        let mut f = Forth::new();
+        assert_eq!(f.eval("-2147483648 1 -"), Err(Error::Overflow));
+        let mut f = Forth::new();
+        assert_eq!(f.eval("65536 65536 *"), Err(Error::Overflow));
+        let mut f = Forth::new();
+        assert_eq!(f.eval("-2147483648 negate"), Err(Error::Overflow));
+        let mut f = Forth::new();
+        assert_eq!(f.eval("-2147483648 abs"), Err(Error::Overflow));
+        let mut f = Forth::new();
+        assert_eq!(f.eval("2147483647 2 1 */"), Err(Error::Overflow));
+    }
+
+    #[test]
+    #[ignore]
+    fn errors_on_dividing_min_by_minus_one() {
+        let mut f = Forth::new();
+        assert_eq!(f.eval("-2147483648 -1 /"), Err(Error::Overflow));
+        let mut f = Forth::new();
+        assert_eq!(f.eval("-2147483648 -1 /mod"), Err(Error::Overflow));
+    }
+
+    #[test]
+    #[ignore]
+    fn leaves_the_stack_unchanged() {
+        let mut f = Forth::new();
+        assert_eq!(f.eval("7 2147483647 1 +"), Err(Error::Overflow));
+        assert_eq!(f.stack(), [7, 2147483647, 1]);
+        assert_eq!(f.eval("0 /"), Err(Error::DivisionByZero));
+        assert_eq!(f.stack(), [7, 2147483647, 1, 0]);
+    }
+
+    #[test]
+    #[ignore]
+    fn wrapping_mode_uses_twos_complement() {
+        let mut f = Forth::new();
+        f.set_arithmetic_mode(ArithmeticMode::Wrapping);
+        assert!(f.eval("2147483647 1 + -2147483648 -1 / -2147483648 negate").is_ok());
+        assert_eq!(f.stack(), [-2147483648, -2147483648, -2147483648]);
+        assert_eq!(f.eval("1 0 /"), Err(Error::DivisionByZero));
+    }
+}
+
+mod memory {
+    use forth::*;
+
+    #[test]
+    #[ignore]
+    fn variables_store_and_fetch_values() {
+        let mut f = Forth::new();
+        assert!(f.eval("variable x 42 x ! x @ 8 x +! x @").is_ok());
+        assert_eq!(f.stack(), [42, 50]);
+    }
+
+    #[test]
+    #[ignore]
+    fn variables_keep_state_between_evals() {
+        let mut f = Forth::new();
+        assert!(f.eval("variable counter").is_ok());
+        assert!(f.eval(": tick 1 counter +! ;").is_ok());
+        assert!(f.eval("tick tick").is_ok());
+        assert!(f.eval("tick counter @").is_ok());
+        assert_eq!(f.stack(), [3]);
+    }
+
+    #[test]
+    #[ignore]
+    fn constants_push_their_value() {
+        let mut f = Forth::new();
+        assert!(f.eval("7 constant seven seven seven *").is_ok());
+        assert_eq!(f.stack(), [49]);
+    }
+
+    #[test]
+    #[ignore]
+    fn create_allot_and_comma_build_arrays() {
+        let mut f = Forth::new();
+        assert!(f.eval("create table 10 , 20 , 30 , create buffer 4 allot").is_ok());
+        assert!(f.eval("table 2 + @ buffer here swap -").is_ok());
+        assert_eq!(f.stack(), [30, 4]);
+        assert_eq!(f.memory(), [10, 20, 30, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    #[ignore]
+    fn errors_on_invalid_addresses() {
+        let mut f = Forth::new();
+        assert_eq!(f.eval("0 @"), Err(Error::InvalidAddress));
+        assert_eq!(f.eval("variable x 1 x 1 + !"), Err(Error::InvalidAddress));
+        assert_eq!(f.eval("-1 @"), Err(Error::InvalidAddress));
+        assert_eq!(f.eval("-5 allot"), Err(Error::InvalidAddress));
+        assert_eq!(f.eval("2147483647 allot"), Err(Error::LimitExceeded(Limit::MemoryCells)));
+    }
+
+    #[test]
+    #[ignore]
+    fn errors_on_missing_or_invalid_names() {
+        let mut f = Forth::new();
+        assert_eq!(f.eval("variable"), Err(Error::InvalidWord));
+        assert_eq!(f.eval("1 constant 2"), Err(Error::InvalidWord));
+        let mut f = Forth::new();
+        assert_eq!(f.eval("constant x"), Err(Error::StackUnderflow));
+    }
+}
+
+mod output {
+    use forth::*;
+    use std::cell::RefCell;
+    use std::io::Write;
+    use std::rc::Rc;
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    #[ignore]
+    fn dot_prints_and_drops_the_top_value() {
+        let mut f = Forth::new();
+        assert!(f.eval("1 2 . .").is_ok());
+        assert_eq!(f.output(), "2 1 ");
+        assert_eq!(f.stack(), []);
+    }
+
+    #[test]
+    #[ignore]
+    fn dot_s_prints_the_stack_without_changing_it() {
+        let mut f = Forth::new();
+        assert!(f.eval("1 2 3 .s").is_ok());
+        assert_eq!(f.output(), "<3> 1 2 3 ");
+        assert_eq!(f.stack(), [1, 2, 3]);
+    }
+
+    #[test]
+    #[ignore]
+    fn characters_and_spacing() {
+        let mut f = Forth::new();
+        assert!(f.eval("72 emit 105 emit space 3 spaces 33 emit cr").is_ok());
+        assert_eq!(f.take_output(), "Hi    !\n");
+        assert_eq!(f.output(), "");
+    }
+
+    #[test]
+    #[ignore]
+    fn string_literals_keep_their_spacing() {
+        let mut f = Forth::new();
+        assert!(f.eval(r#": greet ." Hello,  world!" cr ;"#).is_ok());
+        assert!(f.eval(r#"greet ." bye""#).is_ok());
+        assert_eq!(f.output(), "Hello,  world!\nbye");
+    }
+
+    #[test]
+    #[ignore]
+    fn output_can_be_routed_to_a_writer() {
+        let buffer = SharedBuffer::default();
+        let mut f = Forth::new();
+        f.set_output(buffer.clone());
+        assert!(f.eval(r#"42 . ." done""#).is_ok());
+        assert_eq!(buffer.0.borrow().as_slice(), b"42 done");
+        assert_eq!(f.output(), "");
+    }
+
+    #[test]
+    #[ignore]
+    fn spaces_are_written_in_pieces() {
+        let buffer = SharedBuffer::default();
+        let mut f = Forth::new();
+        f.set_output(buffer.clone());
+        assert!(f.eval("200 spaces -5 spaces").is_ok());
+        assert_eq!(buffer.0.borrow().as_slice(), [b' '; 200]);
+    }
+
+    #[test]
+    #[ignore]
+    fn errors_if_there_is_nothing_to_print() {
+        let mut f = Forth::new();
+        assert_eq!(f.eval("."), Err(Error::StackUnderflow));
+        assert_eq!(f.eval("emit"), Err(Error::StackUnderflow));
+        assert_eq!(f.eval("spaces"), Err(Error::StackUnderflow));
+    }
+}
+
+mod definitions_anywhere {
+    use forth::*;
+
+    #[test]
+    #[ignore]
+    fn definition_in_the_middle_of_a_line() {
+        let mut f = Forth::new();
+        assert!(f.eval("1 2 : foo + ; foo").is_ok());
+        assert_eq!(f.stack(), [3]);
+    }
+
+    #[test]
+    #[ignore]
+    fn several_definitions_on_one_line() {
+        let mut f = Forth::new();
+        assert!(f.eval(": one 1 ; : two one one + ; two one").is_ok());
+        assert_eq!(f.stack(), [2, 1]);
+    }
+
+    #[test]
+    #[ignore]
+    fn definition_spanning_several_evals() {
+        let mut f = Forth::new();
+        assert!(f.eval("5 : square").is_ok());
+        assert!(f.is_compiling());
+        assert!(f.eval("dup").is_ok());
+        assert!(f.eval("* ; square").is_ok());
+        assert!(!f.is_compiling());
+        assert_eq!(f.stack(), [25]);
+    }
+
+    #[test]
+    #[ignore]
+    fn bare_colon_is_an_invalid_word() {
+        let mut f = Forth::new();
+        assert_eq!(f.eval(":"), Err(Error::InvalidWord));
+        assert!(!f.is_compiling());
+    }
+
+    #[test]
+    #[ignore]
+    fn errors_abandon_the_current_definition() {
+        let mut f = Forth::new();
+        assert_eq!(f.eval(": foo bar ;"), Err(Error::UnknownWord));
+        assert!(!f.is_compiling());
+        assert_eq!(f.eval("foo"), Err(Error::UnknownWord));
+    }
+
+    #[test]
+    #[ignore]
+    fn errors_on_misplaced_colon_and_semicolon() {
+        let mut f = Forth::new();
+        assert_eq!(f.eval(": foo : bar ;"), Err(Error::ControlStructureMismatch));
+        assert_eq!(f.eval(";"), Err(Error::ControlStructureMismatch));
+    }
+}
+
+mod shadowing_built_ins {
+    use forth::*;
+
+    #[test]
+    #[ignore]
+    fn overridden_built_in_is_used_in_later_definitions() {
+        let mut f = Forth::new();
+        assert!(f.eval(": + * ;").is_ok());
+        assert!(f.eval(": calc 3 4 + ;").is_ok());
+        assert!(f.eval("calc 3 4 +").is_ok());
+        assert_eq!(f.stack(), [12, 12]);
+    }
+
+    #[test]
+    #[ignore]
+    fn earlier_definitions_keep_the_built_in_meaning() {
+        let mut f = Forth::new();
+        assert!(f.eval(": double dup + ;").is_ok());
+        assert!(f.eval(": dup 1 ;").is_ok());
+        assert!(f.eval(": + - ;").is_ok());
+        assert!(f.eval("5 double").is_ok());
+        assert_eq!(f.stack(), [10]);
+    }
+
+    #[test]
+    #[ignore]
+    fn built_in_can_be_wrapped_by_a_word_of_the_same_name() {
+        let mut f = Forth::new();
+        assert!(f.eval(": swap swap 1 + ;").is_ok());
+        assert!(f.eval(": rotate-pair swap ;").is_ok());
+        assert!(f.eval("1 2 swap 3 4 rotate-pair").is_ok());
+        assert_eq!(f.stack(), [2, 2, 4, 4]);
+    }
+
+    #[test]
+    #[ignore]
+    fn control_words_can_be_shadowed() {
+        let mut f = Forth::new();
+        assert!(f.eval(": i 42 ;").is_ok());
+        assert!(f.eval(": answer i ;").is_ok());
+        assert!(f.eval("i answer").is_ok());
+        assert_eq!(f.stack(), [42, 42]);
+    }
+}
+
+mod return_stack {
+    use forth::*;
+
+    #[test]
+    #[ignore]
+    fn values_can_be_moved_to_and_from_the_return_stack() {
+        let mut f = Forth::new();
+        assert!(f.eval(": under+ >r r@ + r> ;").is_ok());
+        assert!(f.eval("1 2 3 under+").is_ok());
+        assert_eq!(f.stack(), [1, 5, 3]);
+    }
+
+    #[test]
+    #[ignore]
+    fn exit_returns_early() {
+        let mut f = Forth::new();
+        assert!(f.eval(": clamp dup 10 > if drop 10 exit then 1 + ;").is_ok());
+        assert!(f.eval("5 clamp 50 clamp").is_ok());
+        assert_eq!(f.stack(), [6, 10]);
+    }
+
+    #[test]
+    #[ignore]
+    fn exit_leaves_loops() {
+        let mut f = Forth::new();
+        assert!(f.eval(": first-over 100 0 do i dup 3 > if exit then drop loop 0 ;").is_ok());
+        assert!(f.eval("first-over").is_ok());
+        assert_eq!(f.stack(), [4]);
+    }
+
+    #[test]
+    #[ignore]
+    fn recurse_calls_the_word_being_defined() {
+        let mut f = Forth::new();
+        assert!(f.eval(": fact dup 1 > if dup 1 - recurse * then ;").is_ok());
+        assert!(f.eval("5 fact").is_ok());
+        assert_eq!(f.stack(), [120]);
+    }
+
+    #[test]
+    #[ignore]
+    fn recurse_ignores_later_redefinitions() {
+        let mut f = Forth::new();
+        assert!(f.eval(": countdown dup if dup 1 - recurse then ;").is_ok());
+        assert!(f.eval(": run countdown ;").is_ok());
+        assert!(f.eval(": countdown 99 ;").is_ok());
+        assert!(f.eval("2 run").is_ok());
+        assert_eq!(f.stack(), [2, 1, 0]);
+    }
+
+    #[test]
+    #[ignore]
+    fn errors_on_return_stack_underflow() {
+        let mut f = Forth::new();
+        assert!(f.eval(": bad r> ;").is_ok());
+        assert_eq!(f.eval("bad"), Err(Error::ReturnStackUnderflow));
+        assert!(f.eval(": peek r@ ;").is_ok());
+        assert!(f.eval(": outer 1 >r peek r> ;").is_ok());
+        assert_eq!(f.eval("outer"), Err(Error::ReturnStackUnderflow));
+    }
+
+    #[test]
+    #[ignore]
+    fn errors_on_unbalanced_return_stack() {
+        let mut f = Forth::new();
+        assert!(f.eval(": leak >r ;").is_ok());
+        assert_eq!(f.eval("1 leak"), Err(Error::ReturnStackImbalance));
+        assert!(f.eval(": leak-early >r exit ;").is_ok());
+        assert_eq!(f.eval("1 leak-early"), Err(Error::ReturnStackImbalance));
+    }
+
+    #[test]
+    #[ignore]
+    fn errors_when_used_outside_definitions() {
+        let mut f = Forth::new();
+        assert_eq!(f.eval("1 >r"), Err(Error::ControlStructureMismatch));
+        assert_eq!(f.eval("exit"), Err(Error::ControlStructureMismatch));
+    }
+}
+
+mod limits {
+    use forth::*;
+
+    fn limited(limits: ForthLimits) -> Forth {
+        let mut f = Forth::new();
+        f.set_limits(limits);
+        f
+    }
+
+    #[test]
+    #[ignore]
+    fn unbounded_recursion_hits_the_call_depth_limit() {
+        let mut f = Forth::new();
+        assert!(f.eval(": forever recurse ;").is_ok());
+        assert_eq!(f.eval("forever"), Err(Error::LimitExceeded(Limit::CallDepth)));
+    }
+
+    #[test]
+    #[ignore]
+    fn infinite_loops_hit_the_instruction_limit() {
+        let mut f = limited(ForthLimits { max_instructions: 10_000, ..ForthLimits::default() });
+        assert!(f.eval(": spin begin 0 until ;").is_ok());
+        assert_eq!(f.eval("spin"), Err(Error::LimitExceeded(Limit::Instructions)));
+    }
+
+    #[test]
+    #[ignore]
+    fn instruction_budget_is_per_eval() {
+        let mut f = limited(ForthLimits { max_instructions: 3, ..ForthLimits::default() });
+        assert!(f.eval("1 2 +").is_ok());
+        assert!(f.eval("3 4 +").is_ok());
+        assert_eq!(f.eval("1 1 1 1"), Err(Error::LimitExceeded(Limit::Instructions)));
+    }
+
+    #[test]
+    #[ignore]
+    fn errors_on_deep_stacks() {
+        let mut f = limited(ForthLimits { max_stack_depth: 3, ..ForthLimits::default() });
+        assert!(f.eval("1 2 3").is_ok());
+        assert_eq!(f.eval("dup"), Err(Error::LimitExceeded(Limit::StackDepth)));
+        let mut f = limited(ForthLimits { max_stack_depth: 3, ..ForthLimits::default() });
+        assert!(f.eval(": push-forever begin 1 >r 0 until ;").is_ok());
+        assert_eq!(f.eval("push-forever"), Err(Error::LimitExceeded(Limit::StackDepth)));
+    }
+
+    #[test]
+    #[ignore]
+    fn errors_on_large_dictionaries() {
+        let mut f = limited(ForthLimits { max_dictionary_size: 2, ..ForthLimits::default() });
+        assert!(f.eval(": a 1 ; : b 2 ;").is_ok());
+        assert_eq!(f.eval(": c 3 ;"), Err(Error::LimitExceeded(Limit::DictionarySize)));
+        assert_eq!(f.eval("variable v"), Err(Error::LimitExceeded(Limit::DictionarySize)));
+    }
+
+    #[test]
+    #[ignore]
+    fn errors_on_long_definitions() {
+        let mut f = limited(ForthLimits { max_definition_length: 4, ..ForthLimits::default() });
+        assert!(f.eval(": short 1 2 3 4 ;").is_ok());
+        assert_eq!(f.eval(": long 1 2 3 4 5 ;"), Err(Error::LimitExceeded(Limit::DefinitionLength)));
+        assert!(!f.is_compiling());
+    }
+
+    #[test]
+    #[ignore]
+    fn errors_on_large_allocations() {
+        let mut f = limited(ForthLimits { max_memory_cells: 8, ..ForthLimits::default() });
+        assert!(f.eval("8 allot").is_ok());
+        assert_eq!(f.eval("1 allot"), Err(Error::LimitExceeded(Limit::MemoryCells)));
+        assert_eq!(f.eval("variable v"), Err(Error::LimitExceeded(Limit::MemoryCells)));
+        assert_eq!(f.eval(r#": s s" x" ;"#), Err(Error::LimitExceeded(Limit::MemoryCells)));
+    }
+
+    #[test]
+    #[ignore]
+    fn errors_on_large_output() {
+        let mut f = limited(ForthLimits { max_output_bytes: 8, ..ForthLimits::default() });
+        assert!(f.eval("12345 . 1 .").is_ok());
+        assert_eq!(f.eval("123456789 ."), Err(Error::LimitExceeded(Limit::Output)));
+        assert_eq!(f.eval(": loud 100 spaces ; ' loud catch"), Err(Error::LimitExceeded(Limit::Output)));
+        assert_eq!(f.take_output(), "12345 1 ");
+    }
+
+    #[test]
+    #[ignore]
+    fn huge_space_counts_stop_at_the_output_limit() {
+        let mut f = Forth::<i64>::default();
+        assert_eq!(f.eval("100000000000000 spaces"), Err(Error::LimitExceeded(Limit::Output)));
+        assert_eq!(f.output().len(), ForthLimits::default().max_output_bytes);
+    }
+}
+
+mod exceptions {
+    use forth::*;
+
+    #[test]
+    #[ignore]
+    fn catch_pushes_zero_when_nothing_is_thrown() {
+        let mut f = Forth::new();
+        assert!(f.eval(": safe 1 2 + ;").is_ok());
+        assert!(f.eval("' safe catch").is_ok());
+        assert_eq!(f.stack(), [3, 0]);
+    }
+
+    #[test]
+    #[ignore]
+    fn catch_returns_the_thrown_code_and_restores_the_stack_depth() {
+        let mut f = Forth::new();
+        assert!(f.eval(": risky 7 8 9 42 throw ;").is_ok());
+        assert!(f.eval("1 2 ' risky catch").is_ok());
+        assert_eq!(f.stack(), [1, 2, 42]);
+    }
+
+    #[test]
+    #[ignore]
+    fn zero_throw_does_nothing() {
+        let mut f = Forth::new();
+        assert!(f.eval("1 0 throw").is_ok());
+        assert_eq!(f.stack(), [1]);
+    }
+
+    #[test]
+    #[ignore]
+    fn built_in_errors_map_to_standard_codes() {
+        let mut f = Forth::new();
+        assert!(f.eval(": div / ;").is_ok());
+        assert!(f.eval("1 0 ' div catch").is_ok());
+        assert_eq!(f.stack(), [1, 0, -10]);
+        let mut f = Forth::new();
+        assert!(f.eval(": under drop ;").is_ok());
+        assert!(f.eval("' under catch").is_ok());
+        assert_eq!(f.stack(), [-4]);
+        let mut f = Forth::new();
+        assert!(f.eval("2147483647 1 ' + catch").is_ok());
+        assert_eq!(f.stack(), [2147483647, 1, -11]);
+    }
+
+    #[test]
+    #[ignore]
+    fn catch_can_be_used_inside_definitions() {
+        let mut f = Forth::new();
+        assert!(f.eval(": safe-div ['] / catch if drop drop 0 then ;").is_ok());
+        assert!(f.eval("10 2 safe-div 10 0 safe-div").is_ok());
+        assert_eq!(f.stack(), [5, 0]);
+    }
+
+    #[test]
+    #[ignore]
+    fn nested_catches_use_the_innermost_handler() {
+        let mut f = Forth::new();
+        assert!(f.eval(": inner 5 throw ; : middle ['] inner catch 100 + throw ;").is_ok());
+        assert!(f.eval("' middle catch").is_ok());
+        assert_eq!(f.stack(), [105]);
+    }
+
+    #[test]
+    #[ignore]
+    fn execute_runs_an_execution_token() {
+        let mut f = Forth::new();
+        assert!(f.eval(": sq dup * ; 3 ' sq execute ' dup execute").is_ok());
+        assert_eq!(f.stack(), [9, 9]);
+    }
+
+    #[test]
+    #[ignore]
+    fn uncaught_throws_surface_from_eval() {
+        let mut f = Forth::new();
+        assert_eq!(f.eval("1 99 throw"), Err(Error::Thrown(99)));
+        assert!(f.eval(": fail -2 throw ;").is_ok());
+        assert_eq!(f.eval("fail"), Err(Error::Thrown(-2)));
+        assert_eq!(f.eval("1 0 /"), Err(Error::DivisionByZero));
+    }
+
+    #[test]
+    #[ignore]
+    fn instruction_limit_cannot_be_caught() {
+        let mut f = Forth::new();
+        f.set_limits(ForthLimits { max_instructions: 1000, ..ForthLimits::default() });
+        assert!(f.eval(": spin begin 0 until ;").is_ok());
+        assert_eq!(f.eval("' spin catch"), Err(Error::LimitExceeded(Limit::Instructions)));
+    }
+
+    #[test]
+    #[ignore]
+    fn errors_on_invalid_execution_tokens() {
+        let mut f = Forth::new();
+        assert_eq!(f.eval("-1 execute"), Err(Error::InvalidAddress));
+        assert_eq!(f.eval("' nothing"), Err(Error::UnknownWord));
+        let mut f = Forth::new();
+        assert_eq!(f.eval("catch"), Err(Error::StackUnderflow));
+    }
+}
+
+mod atomic_eval {
+    use forth::*;
+
+    #[test]
+    #[ignore]
+    fn failed_line_leaves_the_stack_untouched() {
+        let mut f = Forth::new();
+        assert!(f.eval_atomic("1 2").is_ok());
+        assert_eq!(f.eval_atomic("3 4 0 /"), Err(Error::DivisionByZero));
+        assert_eq!(f.stack(), [1, 2]);
+    }
+
+    #[test]
+    #[ignore]
+    fn failed_line_drops_its_definitions() {
+        let mut f = Forth::new();
+        assert!(f.eval_atomic(": foo 1 ;").is_ok());
+        assert_eq!(f.eval_atomic(": foo 2 ; : bar 3 ; variable v missing"), Err(Error::UnknownWord));
+        assert!(f.eval("foo").is_ok());
+        assert_eq!(f.stack(), [1]);
+        assert_eq!(f.eval("bar"), Err(Error::UnknownWord));
+        assert_eq!(f.eval("v"), Err(Error::UnknownWord));
+        assert_eq!(f.memory(), []);
+    }
+
+    #[test]
+    #[ignore]
+    fn failed_line_restores_the_pending_definition() {
+        let mut f = Forth::new();
+        assert!(f.eval_atomic(": double").is_ok());
+        assert_eq!(f.eval_atomic("dup nothing"), Err(Error::UnknownWord));
+        assert!(f.is_compiling());
+        assert!(f.eval_atomic("2 * ; 4 double").is_ok());
+        assert_eq!(f.stack(), [8]);
+    }
+
+    #[test]
+    #[ignore]
+    fn plain_eval_keeps_partial_results() {
+        let mut f = Forth::new();
+        assert_eq!(f.eval("3 4 0 /"), Err(Error::DivisionByZero));
+        assert_eq!(f.stack(), [3, 4, 0]);
+    }
+}
+
+mod error_reporting {
+    use forth::*;
+
+    #[test]
+    #[ignore]
+    fn located_errors_compare_by_kind() {
+        let mut f = Forth::new();
+        let error = f.eval("1 foo").unwrap_err();
+        assert_eq!(error, Error::UnknownWord);
+        assert_ne!(error, Error::InvalidWord);
+        assert_eq!(error.kind(), &Error::UnknownWord);
+    }
+
+    #[test]
+    #[ignore]
+    fn error_carries_token_and_span() {
+        let mut f = Forth::new();
+        let error = f.eval("1 2\n3 frob 4").unwrap_err();
+        let location = error.location().unwrap();
+        assert_eq!(location.token, "frob");
+        assert_eq!(location.span, 6..10);
+        assert_eq!(location.line, "3 frob 4");
+        assert_eq!(location.line_number, 2);
+        assert_eq!(location.column, 2);
+        assert!(location.trace.is_empty());
+    }
+
+    #[test]
+    #[ignore]
+    fn error_records_executing_words() {
+        let mut f = Forth::new();
+        assert!(f.eval(": inner 0 / ; : outer 1 inner ;").is_ok());
+        let error = f.eval("outer").unwrap_err();
+        assert_eq!(error, Error::DivisionByZero);
+        let location = error.location().unwrap();
+        assert_eq!(location.token, "outer");
+        assert_eq!(location.trace, vec!["OUTER", "INNER"]);
+    }
+
+    #[test]
+    #[ignore]
+    fn caught_errors_leave_no_trace() {
+        let mut f = Forth::new();
+        assert!(f.eval(": bad 0 / ; : safe ['] bad catch ;").is_ok());
+        assert!(f.eval("1 safe").is_ok());
+        let error = f.eval("drop drop drop").unwrap_err();
+        assert!(error.location().unwrap().trace.is_empty());
+    }
+
+    #[test]
+    #[ignore]
+    fn display_underlines_the_token() {
+        let mut f = Forth::new();
+        assert!(f.eval(": inner 0 / ; : outer 1 inner ;").is_ok());
+        let error = f.eval("1 outer").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "division by zero: `outer`\n --> 1:3\n  |\n1 | 1 outer\n  |   ^^^^^\n  = in: OUTER > INNER"
+        );
+    }
+
+    #[test]
+    #[ignore]
+    fn error_implements_std_error() {
+        let mut f = Forth::new();
+        let error: Box<dyn std::error::Error> = Box::new(f.eval("drop").unwrap_err());
+        assert!(error.to_string().starts_with("stack underflow"));
+    }
+}
+
+mod native_words {
+    use forth::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    #[ignore]
+    fn native_word_can_be_called() {
+        let mut f = Forth::new();
+        assert!(f.define_native("sensor", 0, |stack| {
+            stack.push(42);
+            Ok(())
+        }).is_ok());
+        assert!(f.eval("sensor 1 +").is_ok());
+        assert_eq!(f.stack(), [43]);
+    }
+
+    #[test]
+    #[ignore]
+    fn native_word_can_be_used_in_definitions() {
+        let mut f = Forth::new();
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let sink = Rc::clone(&log);
+        assert!(f.define_native("log", 1, move |stack| {
+            sink.borrow_mut().push(stack.pop().unwrap());
+            Ok(())
+        }).is_ok());
+        assert!(f.eval(": report 3 0 do i log loop ;").is_ok());
+        assert!(f.eval("report ' log execute").is_err());
+        assert!(f.eval("7 ' log execute").is_ok());
+        assert_eq!(*log.borrow(), [0, 1, 2, 7]);
+    }
+
+    #[test]
+    #[ignore]
+    fn native_word_arity_is_checked() {
+        let mut f = Forth::new();
+        assert!(f.define_native("add3", 3, |stack| {
+            let sum = stack.drain(stack.len() - 3..).sum();
+            stack.push(sum);
+            Ok(())
+        }).is_ok());
+        assert_eq!(f.eval("1 2 add3"), Err(Error::StackUnderflow));
+        assert!(f.eval("3 add3").is_ok());
+        assert_eq!(f.stack(), [6]);
+    }
+
+    #[test]
+    #[ignore]
+    fn failing_native_word_restores_its_arguments() {
+        let mut f = Forth::new();
+        assert!(f.define_native("fail", 2, |stack| {
+            stack.truncate(stack.len() - 2);
+            Err(Error::Thrown(-100))
+        }).is_ok());
+        assert_eq!(f.eval("1 2 3 fail"), Err(Error::Thrown(-100)));
+        assert_eq!(f.stack(), [1, 2, 3]);
+        assert!(f.eval("' fail catch").is_ok());
+        assert_eq!(f.stack(), [1, 2, 3, -100]);
+    }
+
+    #[test]
+    #[ignore]
+    fn native_word_can_shadow_and_be_shadowed() {
+        let mut f = Forth::new();
+        assert!(f.define_native("dup", 1, |stack| {
+            stack.push(0);
+            Ok(())
+        }).is_ok());
+        assert!(f.eval("5 dup").is_ok());
+        assert_eq!(f.stack(), [5, 0]);
+        assert!(f.eval(": dup 9 ; dup").is_ok());
+        assert_eq!(f.stack(), [5, 0, 9]);
+    }
+
+    #[test]
+    #[ignore]
+    fn native_word_name_must_be_valid() {
+        let mut f = Forth::new();
+        assert_eq!(f.define_native("12", 0, |_| Ok(())), Err(Error::InvalidWord));
+    }
+
+    #[test]
+    #[ignore]
+    fn native_word_respects_dictionary_limit() {
+        let mut f = Forth::new();
+        f.set_limits(ForthLimits { max_dictionary_size: 1, ..ForthLimits::default() });
+        assert!(f.define_native("one", 0, |_| Ok(())).is_ok());
+        assert_eq!(f.define_native("two", 0, |_| Ok(())), Err(Error::LimitExceeded(Limit::DictionarySize)));
+    }
+}
+
+mod introspection {
+    use forth::*;
+
+    #[test]
+    #[ignore]
+    fn words_lists_newest_first() {
+        let mut f = Forth::new();
+        assert!(f.eval(": foo 1 ; variable bar : foo 2 ;").is_ok());
+        let words = f.words();
+        assert_eq!(words[..2], ["FOO", "BAR"]);
+        assert_eq!(words.iter().filter(|name| *name == "FOO").count(), 1);
+        assert!(words.contains(&"DUP".to_string()));
+    }
+
+    #[test]
+    #[ignore]
+    fn words_prints_the_dictionary() {
+        let mut f = Forth::new();
+        assert!(f.eval(": foo 1 ; : bar 2 ; words").is_ok());
+        assert!(f.output().starts_with("BAR FOO "));
+    }
+
+    #[test]
+    #[ignore]
+    fn definition_decompiles_simple_words() {
+        let mut f = Forth::new();
+        assert!(f.eval(": square dup * ; : fourth square square ; 5 constant five").is_ok());
+        assert_eq!(f.definition("fourth").as_deref(), Some(": FOURTH SQUARE SQUARE ;"));
+        assert_eq!(f.definition("five").as_deref(), Some("5 CONSTANT FIVE"));
+        assert_eq!(f.definition("dup").as_deref(), Some("CODE DUP"));
+        assert_eq!(f.definition("missing"), None);
+    }
+
+    #[test]
+    #[ignore]
+    fn definition_shows_data_words_by_their_defining_word() {
+        let mut f = Forth::new();
+        assert!(f.eval("variable v create buf 1. 2constant one 2variable pair").is_ok());
+        assert_eq!(f.definition("v").as_deref(), Some("VARIABLE V"));
+        assert_eq!(f.definition("buf").as_deref(), Some("CREATE BUF"));
+        assert_eq!(f.definition("one").as_deref(), Some("1 0 2CONSTANT ONE"));
+        assert_eq!(f.definition("pair").as_deref(), Some("2VARIABLE PAIR"));
+    }
+
+    #[test]
+    #[ignore]
+    fn definition_keeps_ticks_and_strings() {
+        let mut f = Forth::new();
+        let sources = [": RUN ['] DUP EXECUTE ;", ": SHOW S\" x y\" TYPE ;"];
+        for source in sources {
+            assert!(f.eval(source).is_ok());
+            let name = source.split_whitespace().nth(1).unwrap();
+            assert_eq!(f.definition(name).as_deref(), Some(source));
+        }
+        assert!(f.eval("3 run show").is_ok());
+        assert_eq!(f.stack(), [3, 3]);
+        assert_eq!(f.output(), "x y");
+    }
+
+    #[test]
+    #[ignore]
+    fn definition_recovers_control_structures() {
+        let mut f = Forth::new();
+        let sources = [
+            ": SIGN DUP 0< IF DROP -1 ELSE 0= IF 0 ELSE 1 THEN THEN ;",
+            ": COUNTDOWN BEGIN DUP . 1 - DUP 0= UNTIL DROP ;",
+            ": HALVE BEGIN DUP 1 > WHILE 2 / REPEAT ;",
+            ": GRID 3 0 DO 2 0 DO I J + . LOOP 2 +LOOP ;",
+            ": MAYBE IF BEGIN DUP WHILE 1 - REPEAT THEN ;",
+            ": GREET .\" hello world\" CR ;",
+            ": FACT DUP 1 > IF DUP 1 - RECURSE * THEN ;",
+        ];
+        for source in sources {
+            assert!(f.eval(source).is_ok());
+            let name = source.split_whitespace().nth(1).unwrap();
+            assert_eq!(f.definition(name).as_deref(), Some(source));
+        }
+    }
+
+    #[test]
+    #[ignore]
+    fn see_prints_the_definition() {
+        let mut f = Forth::new();
+        assert!(f.eval(": foo 1 2 + ; see foo").is_ok());
+        assert_eq!(f.output(), ": FOO 1 2 + ;\n");
+    }
+
+    #[test]
+    #[ignore]
+    fn see_is_not_allowed_in_definitions() {
+        let mut f = Forth::new();
+        assert_eq!(f.eval(": foo see dup ;"), Err(Error::InvalidWord));
+    }
+
+    #[test]
+    #[ignore]
+    fn forget_removes_later_words_and_restores_shadowed_ones() {
+        let mut f = Forth::new();
+        assert!(f.eval(": foo 1 ; : foo 2 ; : bar 3 ; forget foo foo").is_ok());
+        assert_eq!(f.stack(), [1]);
+        assert_eq!(f.eval("bar"), Err(Error::UnknownWord));
+    }
+
+    #[test]
+    #[ignore]
+    fn forget_releases_memory() {
+        let mut f = Forth::new();
+        assert!(f.eval("variable a variable b 10 b ! forget b").is_ok());
+        assert_eq!(f.memory(), [0]);
+    }
+
+    #[test]
+    #[ignore]
+    fn built_ins_cannot_be_forgotten() {
+        let mut f = Forth::new();
+        assert_eq!(f.eval("forget dup"), Err(Error::InvalidWord));
+    }
+
+    #[test]
+    #[ignore]
+    fn marker_rolls_back_the_dictionary() {
+        let mut f = Forth::new();
+        assert!(f.eval(": keep 1 ; marker checkpoint : drop-me 2 ; variable v").is_ok());
+        assert!(f.eval("checkpoint keep").is_ok());
+        assert_eq!(f.stack(), [1]);
+        assert_eq!(f.eval("drop-me"), Err(Error::UnknownWord));
+        assert_eq!(f.eval("checkpoint"), Err(Error::UnknownWord));
+        assert_eq!(f.memory(), []);
+    }
+
+    #[test]
+    #[ignore]
+    fn marker_cannot_run_inside_a_definition() {
+        let mut f = Forth::new();
+        assert!(f.eval("marker m : reset m ;").is_ok());
+        assert_eq!(f.eval("reset"), Err(Error::InvalidWord));
+        assert!(f.eval("reset").is_err());
+        assert!(f.eval("m").is_ok());
+        assert_eq!(f.eval("reset"), Err(Error::UnknownWord));
+    }
+
+    #[test]
+    #[ignore]
+    fn failed_atomic_eval_undoes_forget() {
+        let mut f = Forth::new();
+        assert!(f.eval(": foo 1 ;").is_ok());
+        assert!(f.eval_atomic("forget foo missing").is_err());
+        assert!(f.eval("foo").is_ok());
+        assert_eq!(f.stack(), [1]);
+    }
+}
+
+mod images {
+    use forth::*;
+
+    fn image(f: &Forth) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        assert!(f.save_image(&mut bytes).is_ok());
+        bytes
+    }
+
+    #[test]
+    #[ignore]
+    fn image_restores_definitions() {
+        let mut f = Forth::new();
+        assert!(f.eval(": square dup * ; : greet .\" hi\" ; 3 constant three").is_ok());
+        let mut g = Forth::new();
+        assert!(g.load_image(&mut image(&f).as_slice()).is_ok());
+        assert!(g.eval("three square greet").is_ok());
+        assert_eq!(g.stack(), [9]);
+        assert_eq!(g.output(), "hi");
+    }
+
+    #[test]
+    #[ignore]
+    fn image_keeps_shadowed_definitions() {
+        let mut f = Forth::new();
+        assert!(f.eval(": foo 5 ; : bar foo ; : foo 6 ; marker mark : baz 7 ;").is_ok());
+        let mut g = Forth::new();
+        assert!(g.load_image(&mut image(&f).as_slice()).is_ok());
+        assert!(g.eval("foo bar").is_ok());
+        assert_eq!(g.stack(), [6, 5]);
+        assert!(g.eval("mark").is_ok());
+        assert_eq!(g.eval("baz"), Err(Error::UnknownWord));
+        assert_eq!(g.words(), f.words()[2..]);
+    }
+
+    #[test]
+    #[ignore]
+    fn image_keeps_data_space() {
+        let mut f = Forth::new();
+        assert!(f.eval("variable counter 41 counter ! create table 1 , 2 ,").is_ok());
+        let mut g = Forth::new();
+        assert!(g.load_image(&mut image(&f).as_slice()).is_ok());
+        assert_eq!(g.memory(), [41, 1, 2]);
+        assert!(g.eval("1 counter +! counter @ table 1 + @").is_ok());
+        assert_eq!(g.stack(), [42, 2]);
+    }
+
+    #[test]
+    #[ignore]
+    fn stack_is_saved_only_on_request() {
+        let mut f = Forth::new();
+        assert!(f.eval("1 2 3").is_ok());
+        let mut with_stack = Vec::new();
+        assert!(f.save_image_with_stack(&mut with_stack).is_ok());
+        let mut g = Forth::new();
+        assert!(g.eval("9").is_ok());
+        assert!(g.load_image(&mut with_stack.as_slice()).is_ok());
+        assert_eq!(g.stack(), [1, 2, 3]);
+        assert!(g.load_image(&mut image(&f).as_slice()).is_ok());
+        assert_eq!(g.stack(), []);
+    }
+
+    #[test]
+    #[ignore]
+    fn image_rebinds_native_words_by_name() {
+        let mut f = Forth::new();
+        assert!(f.define_native("answer", 0, |stack| {
+            stack.push(42);
+            Ok(())
+        }).is_ok());
+        assert!(f.eval(": twice answer answer + ;").is_ok());
+        let bytes = image(&f);
+        let mut g = Forth::new();
+        assert_eq!(g.load_image(&mut bytes.as_slice()), Err(Error::Image(ImageError::MissingNative)));
+        assert!(g.define_native("answer", 0, |stack| {
+            stack.push(21);
+            Ok(())
+        }).is_ok());
+        assert!(g.load_image(&mut bytes.as_slice()).is_ok());
+        assert!(g.eval("twice").is_ok());
+        assert_eq!(g.stack(), [42]);
+    }
+
+    #[test]
+    #[ignore]
+    fn keeps_how_words_were_defined() {
+        let mut f = Forth::new();
+        assert!(f.eval("variable v 5 constant five : run ['] dup execute ; : show s\" hi\" type ;").is_ok());
+        let mut g = Forth::new();
+        assert!(g.load_image(&mut image(&f).as_slice()).is_ok());
+        for name in ["v", "five", "run", "show"] {
+            assert_eq!(g.definition(name), f.definition(name));
+        }
+        assert!(g.eval("1 run show").is_ok());
+        assert_eq!((g.stack(), g.output()), (&[1, 1][..], "hi"));
+    }
+
+    #[test]
+    #[ignore]
+    fn rejects_foreign_data() {
+        let mut f = Forth::new();
+        assert_eq!(f.load_image(&mut &b"not an image"[..]), Err(Error::Image(ImageError::BadMagic)));
+    }
+
+    #[test]
+    #[ignore]
+    fn rejects_other_versions() {
+        let mut f = Forth::new();
+        let mut bytes = image(&f);
+        bytes[4] = 99;
+        assert_eq!(f.load_image(&mut bytes.as_slice()), Err(Error::Image(ImageError::UnsupportedVersion(99))));
+    }
+
+    #[test]
+    #[ignore]
+    fn rejects_corrupt_images_without_changing_state() {
+        let mut f = Forth::new();
+        assert!(f.eval(": foo 1 ; : bar foo 2 ;").is_ok());
+        let bytes = image(&f);
+        let mut g = Forth::new();
+        assert!(g.eval(": keep 3 ;").is_ok());
+        for len in 8..bytes.len() {
+            assert_eq!(g.load_image(&mut &bytes[..len]), Err(Error::Image(ImageError::Corrupt)));
+        }
+        // The image ends with BAR's `CALL FOO` and `PUSH 2`, then empty memory and no stack.
+        let mut garbage = bytes.clone();
+        let call = garbage.len() - 28;
+        assert_eq!(garbage[call], 2);
+        garbage[call + 1] = 200;
+        assert_eq!(g.load_image(&mut garbage.as_slice()), Err(Error::Image(ImageError::Corrupt)));
+        assert!(g.eval("keep").is_ok());
+        assert_eq!(g.stack(), [3]);
+    }
+}
+
+mod source_syntax {
+    use forth::*;
+
+    #[test]
+    #[ignore]
+    fn paren_comments_are_skipped() {
+        let mut f = Forth::new();
+        assert!(f.eval(": square ( n -- n*n ) dup * ; ( top level ) 3 square").is_ok());
+        assert_eq!(f.stack(), [9]);
+    }
+
+    #[test]
+    #[ignore]
+    fn line_comments_run_to_end_of_line() {
+        let mut f = Forth::new();
+        assert!(f.eval("1 \\ 2 3\n4 \\\n5").is_ok());
+        assert_eq!(f.stack(), [1, 4, 5]);
+    }
+
+    #[test]
+    #[ignore]
+    fn comments_span_lines_in_definitions() {
+        let mut f = Forth::new();
+        assert!(f.eval(": foo ( a\nb ) 1 \\ ignored ;\n2 ;").is_ok());
+        assert!(f.eval("foo").is_ok());
+        assert_eq!(f.stack(), [1, 2]);
+    }
+
+    #[test]
+    #[ignore]
+    fn string_literals_are_stored_in_memory() {
+        let mut f = Forth::new();
+        assert!(f.eval("s\" Hi there\" type").is_ok());
+        assert_eq!(f.output(), "Hi there");
+        assert!(f.eval(": greet s\" hello\" ; greet swap drop").is_ok());
+        assert_eq!(f.stack(), [5]);
+        assert!(f.eval("drop greet type greet type").is_ok());
+        assert_eq!(f.output(), "Hi therehellohello");
+    }
+
+    #[test]
+    #[ignore]
+    fn number_prefixes() {
+        let mut f = Forth::new();
+        assert!(f.eval("$FF $ff #10 %1010 $-10 #-7").is_ok());
+        assert_eq!(f.stack(), [255, 255, 10, 10, -16, -7]);
+    }
+
+    #[test]
+    #[ignore]
+    fn hex_changes_parsing_and_printing() {
+        let mut f = Forth::new();
+        assert!(f.eval("hex ff 10 + dup . decimal .").is_ok());
+        assert_eq!(f.output(), "10F 271 ");
+    }
+
+    #[test]
+    #[ignore]
+    fn base_is_a_variable() {
+        let mut f = Forth::new();
+        assert!(f.eval("2 base ! 101 base @ decimal . -11 .s").is_ok());
+        assert_eq!(f.output(), "2 <2> 5 -11 ");
+        assert!(f.eval("hex base @ decimal").is_ok());
+        assert_eq!(f.stack(), [5, -11, 16]);
+    }
+
+    #[test]
+    #[ignore]
+    fn base_rejects_unusable_radixes() {
+        let mut f = Forth::new();
+        assert_eq!(f.eval("1 base !"), Err(Error::InvalidAddress));
+        assert_eq!(f.eval("2drop 37 base !"), Err(Error::InvalidAddress));
+        assert!(f.eval("2drop 10").is_ok());
+        assert_eq!(f.stack(), [10]);
+    }
+
+    #[test]
+    #[ignore]
+    fn hex_definitions_do_not_shadow_numbers_in_decimal() {
+        let mut f = Forth::new();
+        assert!(f.eval("hex : add-a a + ; decimal 5 add-a").is_ok());
+        assert_eq!(f.stack(), [15]);
+    }
+
+    #[test]
+    #[ignore]
+    fn char_literals_keep_their_case() {
+        let mut f = Forth::new();
+        assert!(f.eval("'a' 'A' char z : q [char] q ; q").is_ok());
+        assert_eq!(f.stack(), [97, 65, 122, 113]);
+        assert!(f.eval("'h' emit 'i' emit").is_ok());
+        assert_eq!(f.output(), "hi");
+    }
+
+    #[test]
+    #[ignore]
+    fn bracket_char_is_compile_only() {
+        let mut f = Forth::new();
+        assert_eq!(f.eval("[char] a"), Err(Error::ControlStructureMismatch));
+    }
+
+    #[test]
+    #[ignore]
+    fn forget_releases_string_space() {
+        let mut f = Forth::new();
+        assert!(f.eval(": msg s\" abc\" ; forget msg").is_ok());
+        assert_eq!(f.memory(), []);
+    }
+}
+
+mod metaprogramming {
+    use forth::*;
+
+    #[test]
+    #[ignore]
+    fn immediate_words_run_while_compiling() {
+        let mut f = Forth::new();
+        assert!(f.eval(": announce .\" compiling \" ; immediate").is_ok());
+        assert!(f.eval(": foo announce 1 ;").is_ok());
+        assert_eq!(f.output(), "compiling ");
+        assert!(f.eval("foo").is_ok());
+        assert_eq!(f.stack(), [1]);
+        assert_eq!(f.output(), "compiling ");
+    }
+
+    #[test]
+    #[ignore]
+    fn immediate_needs_a_definition() {
+        let mut f = Forth::new();
+        assert_eq!(f.eval("immediate"), Err(Error::InvalidWord));
+    }
+
+    #[test]
+    #[ignore]
+    fn brackets_switch_to_interpretation() {
+        let mut f = Forth::new();
+        assert!(f.eval(": foo [ 2 3 + ] literal ;").is_ok());
+        assert_eq!(f.stack(), []);
+        assert!(f.eval("foo").is_ok());
+        assert_eq!(f.stack(), [5]);
+    }
+
+    #[test]
+    #[ignore]
+    fn brackets_span_evals() {
+        let mut f = Forth::new();
+        assert!(f.eval(": foo [").is_ok());
+        assert!(f.is_compiling());
+        assert!(f.eval("6 7 *").is_ok());
+        assert!(f.eval("] literal ; foo").is_ok());
+        assert_eq!(f.stack(), [42]);
+    }
+
+    #[test]
+    #[ignore]
+    fn compile_only_words_at_top_level() {
+        let mut f = Forth::new();
+        assert_eq!(f.eval("1 literal"), Err(Error::ControlStructureMismatch));
+        assert_eq!(f.eval("postpone dup"), Err(Error::ControlStructureMismatch));
+        assert_eq!(f.eval("]"), Err(Error::ControlStructureMismatch));
+        assert_eq!(f.eval(": foo [ : bar"), Err(Error::ControlStructureMismatch));
+    }
+
+    #[test]
+    #[ignore]
+    fn postpone_compiles_ordinary_words() {
+        let mut f = Forth::new();
+        assert!(f.eval(": compile-square postpone dup postpone * ; immediate").is_ok());
+        assert!(f.eval(": square compile-square ; 7 square").is_ok());
+        assert_eq!(f.stack(), [49]);
+        assert_eq!(f.definition("square").as_deref(), Some(": SQUARE DUP * ;"));
+    }
+
+    #[test]
+    #[ignore]
+    fn postpone_of_an_immediate_word_defers_it() {
+        let mut f = Forth::new();
+        assert!(f.eval(": five 5 postpone literal ; immediate").is_ok());
+        assert!(f.eval(": later postpone five ; immediate").is_ok());
+        assert!(f.eval(": foo later ; foo").is_ok());
+        assert_eq!(f.stack(), [5]);
+    }
+
+    #[test]
+    #[ignore]
+    fn user_defined_control_structures() {
+        let mut f = Forth::new();
+        assert!(f.eval(": unless postpone 0= postpone if ; immediate").is_ok());
+        assert!(f.eval(": check unless .\" zero\" then ;").is_ok());
+        assert!(f.eval("0 check 1 check").is_ok());
+        assert_eq!(f.output(), "zero");
+        assert_eq!(f.definition("unless").as_deref(), Some(": UNLESS POSTPONE 0= POSTPONE IF ; IMMEDIATE"));
+    }
+
+    #[test]
+    #[ignore]
+    fn postponed_words_bind_at_definition_time() {
+        let mut f = Forth::new();
+        assert!(f.eval(": one 1 ; : compile-one postpone one ; immediate : one 2 ;").is_ok());
+        assert!(f.eval(": foo compile-one ; foo").is_ok());
+        assert_eq!(f.stack(), [1]);
+    }
+
+    #[test]
+    #[ignore]
+    fn failing_immediate_word_abandons_the_definition() {
+        let mut f = Forth::new();
+        assert!(f.eval(": boom 1 0 / ; immediate").is_ok());
+        assert_eq!(f.eval(": foo boom ;"), Err(Error::DivisionByZero));
+        assert!(!f.is_compiling());
+    }
+
+    #[test]
+    #[ignore]
+    fn cannot_forget_words_while_compiling() {
+        let mut f = Forth::new();
+        assert_eq!(f.eval("marker m immediate : w 1 ; : x w m ;"), Err(Error::InvalidWord));
+        assert_eq!(f.eval("x"), Err(Error::UnknownWord));
+        assert_eq!(f.eval(": y w [ forget w ] ;"), Err(Error::InvalidWord));
+        assert_eq!(f.eval("y"), Err(Error::UnknownWord));
+        assert!(f.eval("w").is_ok());
+        assert_eq!(f.stack(), [1]);
+    }
+
+    #[test]
+    #[ignore]
+    fn mutually_postponing_immediate_words_hit_the_call_depth_limit() {
+        let source = ": x postpone then ; immediate : then postpone x ; immediate : y then ;";
+        let mut f = Forth::new();
+        assert_eq!(f.eval(source), Err(Error::LimitExceeded(Limit::CallDepth)));
+        assert!(!f.is_compiling());
+        let mut f = Forth::new();
+        f.set_limits(ForthLimits { max_call_depth: 64, ..ForthLimits::default() });
+        assert_eq!(f.eval(source), Err(Error::LimitExceeded(Limit::CallDepth)));
+        assert!(f.eval(": z 1 ; z").is_ok());
+        assert_eq!(f.stack(), [1]);
+    }
+
+    #[test]
+    #[ignore]
+    fn tick_and_execute_work_with_immediate_words() {
+        let mut f = Forth::new();
+        assert!(f.eval(": seven 7 ; immediate ' seven execute").is_ok());
+        assert_eq!(f.stack(), [7]);
+    }
+
+    #[test]
+    #[ignore]
+    fn immediate_flag_survives_images() {
+        let mut f = Forth::new();
+        assert!(f.eval(": unless postpone 0= postpone if ; immediate").is_ok());
+        let mut bytes = Vec::new();
+        assert!(f.save_image(&mut bytes).is_ok());
+        let mut g = Forth::new();
+        assert!(g.load_image(&mut bytes.as_slice()).is_ok());
+        assert!(g.eval(": check unless 1 then ; 0 check").is_ok());
+        assert_eq!(g.stack(), [1]);
+    }
+}
+
+mod cell_types {
+    use forth::*;
+    use std::num::Wrapping;
+
+    #[test]
+    #[ignore]
+    fn i64_cells_hold_values_past_32_bits() {
+        let mut f = Forth::<i64>::default();
+        assert!(f.eval("2147483647 1 + 2 *").is_ok());
+        assert_eq!(f.stack(), [4294967296]);
+        assert_eq!(f.eval("2147483648 *"), Err(Error::Overflow));
+    }
+
+    #[test]
+    #[ignore]
+    fn i128_cells() {
+        let mut f = Forth::<i128>::default();
+        assert!(f.eval("9223372036854775808 dup * .").is_ok());
+        assert_eq!(f.output(), "85070591730234615865843651857942052864 ");
+    }
+
+    #[test]
+    #[ignore]
+    fn wrapping_u16_cells_are_modular() {
+        let mut f = Forth::<Wrapping<u16>>::default();
+        assert!(f.eval("65535 1 + 0 1 - -1 300 300 *").is_ok());
+        assert_eq!(f.stack(), [Wrapping(0), Wrapping(65535), Wrapping(65535), Wrapping(24464)]);
+        assert_eq!(f.eval("65536"), Err(Error::UnknownWord));
+    }
+
+    #[test]
+    #[ignore]
+    fn wrapping_u16_cells_still_check_division() {
+        let mut f = Forth::<Wrapping<u16>>::default();
+        assert_eq!(f.eval("1 0 /"), Err(Error::DivisionByZero));
+        assert!(f.eval("drop drop 7 2 /mod").is_ok());
+        assert_eq!(f.stack(), [Wrapping(1), Wrapping(3)]);
+    }
+
+    #[test]
+    #[ignore]
+    fn wrapping_u16_cells_print_unsigned() {
+        let mut f = Forth::<Wrapping<u16>>::default();
+        assert!(f.eval("-1 . hex -1 .").is_ok());
+        assert_eq!(f.output(), "65535 FFFF ");
+    }
+
+    #[test]
+    #[ignore]
+    fn wrapping_u16_cells_run_definitions_and_loops() {
+        let mut f = Forth::<Wrapping<u16>>::default();
+        assert!(f.eval(": sum 0 swap 0 do i + loop ; 400 sum").is_ok());
+        assert_eq!(f.stack(), [Wrapping(14264)]);
+        assert!(f.eval("variable v 5 v ! v @ 1 = 0=").is_ok());
+        assert_eq!(f.stack(), [Wrapping(14264), Wrapping(65535)]);
+    }
+
+    #[test]
+    #[ignore]
+    fn wrapping_u16_memory_stops_below_the_base_slot() {
+        let mut f = Forth::<Wrapping<u16>>::default();
+        assert_eq!(f.eval("7 , 65535 allot"), Err(Error::LimitExceeded(Limit::MemoryCells)));
+        assert!(f.eval("drop variable x 5 x ! 0 @ x @").is_ok());
+        assert_eq!(f.stack(), [Wrapping(7), Wrapping(5)]);
+        let mut g = Forth::<Wrapping<u16>>::default();
+        assert!(g.eval("65534 allot 42 , here 65534 @ base @").is_ok());
+        assert_eq!(g.stack(), [Wrapping(65535), Wrapping(42), Wrapping(10)]);
+        assert_eq!(g.eval("0 ,"), Err(Error::LimitExceeded(Limit::MemoryCells)));
+    }
+
+    #[test]
+    #[ignore]
+    fn wrapping_u16_dictionary_stops_when_tokens_run_out() {
+        let mut f = Forth::<Wrapping<u16>>::default();
+        let defined = (0..70_000).take_while(|_| f.eval(": w ;").is_ok()).count();
+        assert!(defined < 65536);
+        assert_eq!(f.eval(": w ;"), Err(Error::LimitExceeded(Limit::DictionarySize)));
+        assert!(f.eval("' w").is_ok());
+        assert_eq!(f.stack(), [Wrapping(65535)]);
+    }
+
+    #[test]
+    #[ignore]
+    fn mul_div_uses_a_wide_intermediate() {
+        let mut f = Forth::<i64>::default();
+        assert!(f.eval("9223372036854775807 4 8 */").is_ok());
+        assert_eq!(f.stack(), [4611686018427387903]);
+    }
+
+    #[test]
+    #[ignore]
+    fn scaled_division_overflow_with_128_bit_cells() {
+        let mut f = Forth::<i128>::default();
+        let input = "170141183460469231731687303715884105727 negate 1 - 1 -1 */";
+        assert_eq!(f.eval(input), Err(Error::Overflow));
+        let mut f = Forth::<i128>::default();
+        f.set_arithmetic_mode(ArithmeticMode::Wrapping);
+        assert!(f.eval(input).is_ok());
+        assert_eq!(f.stack(), [i128::MIN]);
+        assert!(f.eval("drop 170141183460469231731687303715884105727 2 1 */").is_ok());
+        assert_eq!(f.stack(), [-2]);
+    }
+
+    #[test]
+    #[ignore]
+    fn huge_pick_and_roll_depths_underflow() {
+        let mut f = Forth::<i128>::default();
+        assert_eq!(f.eval("18446744073709551615 pick"), Err(Error::StackUnderflow));
+        let mut f = Forth::<i128>::default();
+        assert_eq!(f.eval("1 18446744073709551615 roll"), Err(Error::StackUnderflow));
+    }
+
+    #[test]
+    #[ignore]
+    fn native_words_use_the_cell_type() {
+        let mut f = Forth::<i64>::default();
+        assert!(f.define_native("big", 0, |stack| {
+            stack.push(1 << 40);
+            Ok(())
+        }).is_ok());
+        assert!(f.eval("big 1 +").is_ok());
+        assert_eq!(f.stack(), [(1 << 40) + 1]);
+    }
+
+    #[test]
+    #[ignore]
+    fn images_move_between_cell_types_when_values_fit() {
+        let mut f = Forth::new();
+        assert!(f.eval(": big 2000000000 ; variable v 7 v !").is_ok());
+        let mut bytes = Vec::new();
+        assert!(f.save_image(&mut bytes).is_ok());
+        let mut g = Forth::<i64>::default();
+        assert!(g.load_image(&mut bytes.as_slice()).is_ok());
+        assert!(g.eval("big big + v @").is_ok());
+        assert_eq!(g.stack(), [4000000000, 7]);
+        let mut h = Forth::<Wrapping<u16>>::default();
+        assert_eq!(h.load_image(&mut bytes.as_slice()), Err(Error::Image(ImageError::Corrupt)));
+    }
+}
+
+mod double_cells {
+    use forth::*;
+
+    #[test]
+    #[ignore]
+    fn literals_with_a_trailing_dot_push_two_cells() {
+        let mut f = Forth::new();
+        assert!(f.eval("1. -1. 4294967296. hex 10. decimal").is_ok());
+        assert_eq!(f.stack(), [1, 0, -1, -1, 0, 1, 16, 0]);
+    }
+
+    #[test]
+    #[ignore]
+    fn addition_and_subtraction_carry_between_cells() {
+        let mut f = Forth::new();
+        assert!(f.eval("4294967295. 1. d+ 0. 1. d- 5. dnegate").is_ok());
+        assert_eq!(f.stack(), [0, 1, -1, -1, -5, -1]);
+    }
+
+    #[test]
+    #[ignore]
+    fn print() {
+        let mut f = Forth::new();
+        assert!(f.eval("1. 4294967296. d+ d. -7. d.").is_ok());
+        assert_eq!(f.output(), "4294967297 -7 ");
+    }
+
+    #[test]
+    #[ignore]
+    fn mixed_multiplication() {
+        let mut f = Forth::new();
+        assert!(f.eval("2000000000 3 m* -2 3 m* -1 2 um*").is_ok());
+        assert_eq!(f.stack(), [1705032704, 1, -6, -1, -2, 1]);
+    }
+
+    #[test]
+    #[ignore]
+    fn unsigned_division() {
+        let mut f = Forth::new();
+        assert!(f.eval("10. 3 um/mod -2 1 2 um/mod").is_ok());
+        assert_eq!(f.stack(), [1, 3, 0, -1]);
+    }
+
+    #[test]
+    #[ignore]
+    fn symmetric_and_floored_division() {
+        let mut f = Forth::new();
+        assert!(f.eval("-7. 2 sm/rem -7. 2 fm/mod 7. -2 fm/mod").is_ok());
+        assert_eq!(f.stack(), [-1, -3, 1, -4, -1, -4]);
+    }
+
+    #[test]
+    #[ignore]
+    fn division_errors() {
+        let mut f = Forth::new();
+        assert_eq!(f.eval("1. 0 um/mod"), Err(Error::DivisionByZero));
+        assert_eq!(f.eval("1. 0 fm/mod"), Err(Error::DivisionByZero));
+        assert_eq!(f.eval("0 1 1 um/mod"), Err(Error::Overflow));
+        assert_eq!(f.eval("0 1 1 sm/rem"), Err(Error::Overflow));
+    }
+
+    #[test]
+    #[ignore]
+    fn overflow_follows_the_arithmetic_mode() {
+        let mut f = Forth::new();
+        assert_eq!(f.eval("-1 2147483647 1. d+"), Err(Error::Overflow));
+        f.set_arithmetic_mode(ArithmeticMode::Wrapping);
+        assert!(f.eval("d+").is_ok());
+        assert_eq!(f.stack(), [0, -2147483648]);
+    }
+
+    #[test]
+    #[ignore]
+    fn errors_on_missing_cells() {
+        assert_eq!(Forth::new().eval("1 2 3 d+"), Err(Error::StackUnderflow));
+        assert_eq!(Forth::new().eval("1 d."), Err(Error::StackUnderflow));
+        assert_eq!(Forth::new().eval("1 um/mod"), Err(Error::StackUnderflow));
+    }
+
+    #[test]
+    #[ignore]
+    fn double_constants_and_variables() {
+        let mut f = Forth::new();
+        assert!(f.eval("4294967297. 2constant big 2variable pair big pair 2! pair 2@ pair @").is_ok());
+        assert_eq!(f.stack(), [1, 1, 1]);
+        assert!(f.eval("3 4 pair 2! pair @ pair 1 + @").is_ok());
+        assert_eq!(f.stack(), [1, 1, 1, 4, 3]);
+    }
+
+    #[test]
+    #[ignore]
+    fn doubles_in_definitions() {
+        let mut f = Forth::new();
+        assert!(f.eval(": big 4294967296. ; big 1. d+").is_ok());
+        assert_eq!(f.stack(), [1, 1]);
+        assert_eq!(f.eval(": bad 2constant x ;"), Err(Error::InvalidWord));
+    }
+
+    #[test]
+    #[ignore]
+    fn doubles_span_two_wider_cells() {
+        let mut f = Forth::<i64>::default();
+        assert!(f.eval("9223372036854775807 2 m* d.").is_ok());
+        assert_eq!(f.output(), "18446744073709551614 ");
+    }
+}
+
+mod debugging {
+    use forth::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn record(f: &mut Forth) -> Rc<RefCell<Vec<TraceEvent>>> {
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let sink = Rc::clone(&events);
+        f.set_trace_hook(move |event| sink.borrow_mut().push(event.clone()));
+        events
+    }
+
+    #[test]
+    #[ignore]
+    fn trace_hook_reports_each_word_with_its_stack_effect() {
+        let mut f = Forth::new();
+        assert!(f.eval(": sq dup * ;").is_ok());
+        let events = record(&mut f);
+        assert!(f.eval("3 sq").is_ok());
+        let words: Vec<_> = events.borrow().iter().map(|event| (event.word.clone(), event.depth)).collect();
+        assert_eq!(words, [("3".into(), 0), ("DUP".into(), 1), ("*".into(), 1), ("SQ".into(), 0)]);
+        let last = events.borrow().last().cloned().unwrap();
+        assert_eq!((last.before, last.after), (vec![3], vec![9]));
+    }
+
+    #[test]
+    #[ignore]
+    fn cleared_trace_hook_is_not_called() {
+        let mut f = Forth::new();
+        let events = record(&mut f);
+        f.clear_trace_hook();
+        assert!(f.eval("1 2 +").is_ok());
+        assert!(events.borrow().is_empty());
+    }
+
+    #[test]
+    #[ignore]
+    fn step_runs_one_instruction_at_a_time() {
+        let mut f = Forth::new();
+        assert!(f.eval(": sq dup * ;").is_ok());
+        f.start("3 sq 1 +");
+        assert!(f.is_stepping());
+        let mut stacks = Vec::new();
+        while f.step() == Ok(Step::Paused) {
+            stacks.push(f.stack().to_vec());
+        }
+        assert_eq!(stacks, [vec![3], vec![3], vec![3, 3], vec![9], vec![9], vec![9, 1]]);
+        assert_eq!(f.stack(), [10]);
+        assert!(!f.is_stepping());
+        assert_eq!(f.step(), Ok(Step::Finished));
+    }
+
+    #[test]
+    #[ignore]
+    fn stepping_through_definitions_and_control_flow() {
+        let mut f = Forth::new();
+        f.start(": count 0 3 0 do 1 + loop ; count");
+        assert_eq!(f.resume(), Ok(Step::Finished));
+        assert_eq!(f.stack(), [3]);
+    }
+
+    #[test]
+    #[ignore]
+    fn breakpoints_pause_on_entry() {
+        let mut f = Forth::new();
+        assert!(f.eval(": sq dup * ; : quad sq sq ;").is_ok());
+        assert!(f.set_breakpoint("sq").is_ok());
+        f.start("2 quad");
+        assert_eq!(f.resume(), Ok(Step::Breakpoint("SQ".to_string())));
+        assert_eq!(f.stack(), [2]);
+        assert_eq!(f.resume(), Ok(Step::Breakpoint("SQ".to_string())));
+        assert_eq!(f.stack(), [4]);
+        assert_eq!(f.resume(), Ok(Step::Finished));
+        assert_eq!(f.stack(), [16]);
+        assert!(f.clear_breakpoint("SQ"));
+        f.start("quad");
+        assert_eq!(f.resume(), Ok(Step::Finished));
+        assert_eq!(f.stack(), [65536]);
+    }
+
+    #[test]
+    #[ignore]
+    fn breakpoints_need_a_colon_definition() {
+        let mut f = Forth::new();
+        assert_eq!(f.set_breakpoint("dup"), Err(Error::InvalidWord));
+        assert_eq!(f.set_breakpoint("nope"), Err(Error::UnknownWord));
+        assert!(!f.clear_breakpoint("nope"));
+    }
+
+    #[test]
+    #[ignore]
+    fn errors_end_the_session_with_a_location() {
+        let mut f = Forth::new();
+        assert!(f.eval(": boom 0 / ;").is_ok());
+        f.start("1 boom");
+        let error = f.resume().unwrap_err();
+        assert_eq!(error, Error::DivisionByZero);
+        let location = error.location().unwrap();
+        assert_eq!((location.token.as_str(), location.trace.clone()), ("boom", vec!["BOOM".to_string()]));
+        assert!(!f.is_stepping());
+    }
+
+    #[test]
+    #[ignore]
+    fn catch_works_while_stepping() {
+        let mut f = Forth::new();
+        assert!(f.eval(": boom 7 throw ;").is_ok());
+        f.start("' boom catch");
+        assert_eq!(f.resume(), Ok(Step::Finished));
+        assert_eq!(f.stack(), [7]);
+    }
+}
+
+mod source_files {
+    use forth::*;
+    use std::collections::HashMap;
+
+    struct Files(HashMap<&'static str, &'static str>);
+
+    impl SourceResolver for Files {
+        fn resolve(&self, name: &str) -> Option<Source> {
+            self.0.get(name).map(|text| Source { name: name.to_string(), text: text.to_string() })
+        }
+    }
+
+    fn forth_with(files: &[(&'static str, &'static str)]) -> Forth {
+        let mut f = Forth::new();
+        f.set_resolver(Files(files.iter().copied().collect()));
+        f
+    }
+
+    #[test]
+    #[ignore]
+    fn eval_file_runs_a_resolved_file() {
+        let mut f = forth_with(&[("math.fs", ": sq dup * ;\n3 sq")]);
+        assert!(f.eval_file("math.fs").is_ok());
+        assert_eq!(f.stack(), [9]);
+    }
+
+    #[test]
+    #[ignore]
+    fn include_runs_a_file_every_time() {
+        let mut f = forth_with(&[("one.fs", "1")]);
+        assert!(f.eval("include one.fs include one.fs").is_ok());
+        assert_eq!(f.stack(), [1, 1]);
+    }
+
+    #[test]
+    #[ignore]
+    fn require_runs_a_file_once() {
+        let mut f = forth_with(&[("one.fs", "1"), ("lib.fs", "require one.fs 2")]);
+        assert!(f.eval("include one.fs require one.fs require lib.fs require lib.fs").is_ok());
+        assert_eq!(f.stack(), [1, 2]);
+    }
+
+    #[test]
+    #[ignore]
+    fn failed_atomic_eval_forgets_required_files() {
+        let mut f = forth_with(&[("lib.fs", ": lib 7 ;")]);
+        assert!(f.eval_atomic("require lib.fs 1 0 /").is_err());
+        assert!(f.eval("require lib.fs lib").is_ok());
+        assert_eq!(f.stack(), [7]);
+    }
+
+    #[test]
+    #[ignore]
+    fn missing_files() {
+        let mut f = forth_with(&[]);
+        assert_eq!(f.eval_file("nope.fs"), Err(Error::Include(IncludeError::NotFound)));
+        assert_eq!(f.eval("include nope.fs"), Err(Error::Include(IncludeError::NotFound)));
+        assert_eq!(Forth::new().eval_file("nope.fs"), Err(Error::Include(IncludeError::NotFound)));
+    }
+
+    #[test]
+    #[ignore]
+    fn include_cycles_are_detected() {
+        let mut f = forth_with(&[("a.fs", "include b.fs"), ("b.fs", "1\ninclude a.fs")]);
+        let error = f.eval_file("a.fs").unwrap_err();
+        assert_eq!(error, Error::Include(IncludeError::Cycle));
+        let location = error.location().unwrap();
+        assert_eq!((location.file.as_deref(), location.line_number), (Some("b.fs"), 2));
+        let mut f = forth_with(&[("self.fs", "require self.fs")]);
+        assert_eq!(f.eval_file("self.fs"), Err(Error::Include(IncludeError::Cycle)));
+    }
+
+    #[test]
+    #[ignore]
+    fn errors_report_the_file_and_line() {
+        let mut f = forth_with(&[("bad.fs", ": ok ;\n1 0 /")]);
+        let error = f.eval("include bad.fs").unwrap_err();
+        assert_eq!(error, Error::DivisionByZero);
+        let location = error.location().unwrap();
+        assert_eq!(location.file.as_deref(), Some("bad.fs"));
+        assert_eq!((location.line_number, location.token.as_str()), (2, "/"));
+        assert!(error.to_string().contains("--> bad.fs:2:5"));
+    }
+
+    #[test]
+    #[ignore]
+    fn include_is_not_allowed_in_definitions() {
+        let mut f = forth_with(&[("one.fs", "1")]);
+        assert_eq!(f.eval(": foo include one.fs ;"), Err(Error::InvalidWord));
+        assert_eq!(f.eval("include"), Err(Error::InvalidWord));
+    }
+
+    #[test]
+    #[ignore]
+    fn directory_resolver_stays_inside_its_root() {
+        let dir = std::env::temp_dir().join(format!("forth-include-{}", std::process::id()));
+        let root = dir.join("root");
+        std::fs::create_dir_all(root.join("lib")).unwrap();
+        std::fs::write(root.join("lib/two.fs"), "2").unwrap();
+        std::fs::write(dir.join("secret.fs"), "42").unwrap();
+        let mut f = Forth::new();
+        f.set_resolver(DirectoryResolver::new(&root));
+        assert!(f.eval("include lib/two.fs require lib/../lib/two.fs").is_ok());
+        assert_eq!(f.stack(), [2]);
+        assert_eq!(f.eval("include ../secret.fs"), Err(Error::Include(IncludeError::NotFound)));
+        let secret = dir.join("secret.fs");
+        assert_eq!(f.eval_file(secret.to_str().unwrap()), Err(Error::Include(IncludeError::NotFound)));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
+
+#[cfg(feature = "float")]
+mod floats {
+    use forth::*;
+
+    #[test]
+    #[ignore]
+    fn float_literals_go_to_the_float_stack() {
+        let mut f = Forth::new();
+        assert!(f.eval("1 1.5e0 2E -3.25E-1").is_ok());
+        assert_eq!(f.stack(), [1]);
+        assert_eq!(f.float_stack(), [1.5, 2.0, -0.325]);
+    }
+
+    #[test]
+    #[ignore]
+    fn arithmetic() {
+        let mut f = Forth::new();
+        assert!(f.eval("1.5e0 2e0 f+ 5e0 f* 1e0 f- 2e0 f/ 16e0 fsqrt").is_ok());
+        assert_eq!(f.float_stack(), [8.25, 4.0]);
+    }
+
+    #[test]
+    #[ignore]
+    fn stack_manipulation() {
+        let mut f = Forth::new();
+        assert!(f.eval("1e0 2e0 fswap fdup 3e0 fdrop").is_ok());
+        assert_eq!(f.float_stack(), [2.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    #[ignore]
+    fn comparison_leaves_a_flag_on_the_data_stack() {
+        let mut f = Forth::new();
+        assert!(f.eval("1e0 2e0 f< 2e0 1e0 f<").is_ok());
+        assert_eq!(f.stack(), [-1, 0]);
+        assert!(f.float_stack().is_empty());
+    }
+
+    #[test]
+    #[ignore]
+    fn print() {
+        let mut f = Forth::new();
+        assert!(f.eval("2.5e0 f. 3e0 f.").is_ok());
+        assert_eq!(f.output(), "2.5 3 ");
+        assert!(f.float_stack().is_empty());
+    }
+
+    #[test]
+    #[ignore]
+    fn underflow() {
+        let mut f = Forth::new();
+        assert_eq!(f.eval("1 2 f+"), Err(Error::StackUnderflow));
+        assert_eq!(f.eval("1e0 f+"), Err(Error::StackUnderflow));
+        assert_eq!(f.eval("fdrop fdrop"), Err(Error::StackUnderflow));
+        assert_eq!(f.eval("f."), Err(Error::StackUnderflow));
+    }
+
+    #[test]
+    #[ignore]
+    fn division_by_zero() {
+        let mut f = Forth::new();
+        assert_eq!(f.eval("1e0 0e0 f/"), Err(Error::DivisionByZero));
+        assert_eq!(f.float_stack(), [1.0, 0.0]);
+    }
+
+    #[test]
+    #[ignore]
+    fn floats_in_definitions() {
+        let mut f = Forth::new();
+        assert!(f.eval(": half 5e-1 f* ; 3e0 half").is_ok());
+        assert_eq!(f.float_stack(), [1.5]);
+        assert_eq!(f.definition("half").as_deref(), Some(": HALF 5E-1 F* ;"));
+    }
+
+    #[test]
+    #[ignore]
+    fn numbers_without_an_exponent_are_not_floats() {
+        let mut f = Forth::new();
+        assert_eq!(f.eval("1.5"), Err(Error::UnknownWord));
+        assert!(f.eval("hex 1e").is_ok());
+        assert_eq!(f.stack(), [30]);
+        assert!(f.float_stack().is_empty());
+    }
+
+    #[test]
+    #[ignore]
+    fn failed_atomic_eval_restores_the_float_stack() {
+        let mut f = Forth::new();
+        assert!(f.eval("1e0").is_ok());
+        assert!(f.eval_atomic("2e0 3e0 0e0 f/").is_err());
+        assert_eq!(f.float_stack(), [1.0]);
+    }
+
+    #[test]
+    #[ignore]
+    fn images_keep_float_literals() {
+        let mut f = Forth::new();
+        assert!(f.eval(": gain 2.75e0 ;").is_ok());
+        let mut bytes = Vec::new();
+        assert!(f.save_image(&mut bytes).is_ok());
+        let mut g = Forth::new();
+        assert!(g.load_image(&mut bytes.as_slice()).is_ok());
+        assert!(g.eval("gain").is_ok());
+        assert_eq!(g.float_stack(), [2.75]);
+    }
+}